@@ -0,0 +1,105 @@
+struct Uniforms {
+    nAgents: u32,
+    sizeX: u32,
+    sizeY: u32,
+    n_species: u32,
+    n_channels: u32,
+    decay: f32,
+    repellent_decay: f32,
+    sens_range_min: f32,
+    sens_range_max: f32,
+    sense_steps: f32,
+    seed: u32,
+    deposit_mode: u32,
+    boundary: u32,
+    color_sampling: u32,
+    color_blend: f32,
+    consumption: f32,
+    regrowth: f32,
+    nutriment_diffusion: f32,
+    colormap: u32,
+    tone_mapping: u32,
+    exposure: f32,
+    gamma: f32,
+    white: f32,
+    nutriment_opacity: f32,
+    nutriment_blend: u32,
+    trails_opacity: f32,
+    trails_blend: u32,
+    obstacles_opacity: f32,
+    obstacles_blend: u32,
+    deposit_scale: f32,
+};
+
+@group(0) @binding(0) var<storage, read> nutriment_in: array<f32>;
+@group(0) @binding(1) var<storage, read_write> nutriment_out: array<f32>;
+@group(0) @binding(2) var<uniform> uniforms: Uniforms;
+// agents passing each cell in this step, cleared for the next step
+@group(0) @binding(3) var<storage, read_write> visits: array<u32>;
+// initial nutriment, the level regrowth tends to
+@group(0) @binding(4) var<storage, read> capacity: array<f32>;
+// 1 for walls
+@group(0) @binding(5) var<storage, read> obstacles: array<u32>;
+
+// uniforms.boundary
+let BOUNDARY_WRAP: u32 = 1u;
+let BOUNDARY_REFLECT: u32 = 2u;
+
+@compute
+@workgroup_size(256)
+fn main(@builtin(global_invocation_id) gId: vec3<u32>,
+        @builtin(num_workgroups) nWg: vec3<u32>)
+{
+    // One invocation per cell, rows of workgroups for large grids
+    let cells = uniforms.sizeX * uniforms.sizeY;
+    let i = gId.x + gId.y * nWg.x * 256u;
+    if (i >= cells) {
+        return;
+    }
+    let passed = f32(visits[i]);
+    visits[i] = 0u;
+    // walls keep their nutriment, it can't be sensed or reached
+    if (obstacles[i] != 0u) {
+        nutriment_out[i] = nutriment_in[i];
+        return;
+    }
+
+    var y0: u32 = i / uniforms.sizeX;
+    var x0: u32 = i - y0*uniforms.sizeX;
+    // mean of the 3x3 neighbours inside the grid and not in walls
+    var sum = 0.;
+    var n = 0.;
+    for (var dx=-1; dx<2; dx=dx+1){
+        for(var dy=-1; dy<2; dy=dy+1){
+            let size_x = i32(uniforms.sizeX);
+            let size_y = i32(uniforms.sizeY);
+            var x = i32(x0) + dx;
+            var y = i32(y0) + dy;
+            if (x < 0 || x >= size_x || y < 0 || y >= size_y) {
+                if (uniforms.boundary == BOUNDARY_WRAP) {
+                    x = (x + size_x) % size_x;
+                    y = (y + size_y) % size_y;
+                } else if (uniforms.boundary == BOUNDARY_REFLECT) {
+                    x = clamp(x, 0, size_x - 1);
+                    y = clamp(y, 0, size_y - 1);
+                } else {
+                    continue;
+                }
+            }
+            let n_idx = u32(x) + u32(y)*uniforms.sizeX;
+            if (obstacles[n_idx] != 0u) {
+                continue;
+            }
+            sum = sum + nutriment_in[n_idx];
+            n = n + 1.;
+    }}
+    let d = uniforms.nutriment_diffusion;
+    var value = (1. - d) * nutriment_in[i] + d * sum / n;
+
+    // eaten by each agent passing the cell
+    value = max(value - uniforms.consumption * passed, 0.);
+
+    // grow back towards the initial nutriment
+    value = value + uniforms.regrowth * (capacity[i] - value);
+    nutriment_out[i] = max(value, 0.);
+}
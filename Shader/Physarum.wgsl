@@ -1,41 +1,88 @@
+struct Color {
+    r: f32,
+    g: f32,
+    b: f32
+}
+
 struct Agent {
     x: f32,
     y: f32,
     phi: f32,
+    species: u32,
+    color: Color,
 };
 
 struct Uniforms {
     nAgents: u32,
     sizeX: u32,
     sizeY: u32,
-    deposit: f32,
+    n_species: u32,
+    n_channels: u32,
     decay: f32,
-    v: f32,
-    phi_sens: f32,
-    turn_speed: f32,
+    repellent_decay: f32,
     sens_range_min: f32,
     sens_range_max: f32,
     sense_steps: f32,
-    w_nutriment: f32,
     seed: u32,
+    deposit_mode: u32,
+    boundary: u32,
+    color_sampling: u32,
+    color_blend: f32,
+    consumption: f32,
+    regrowth: f32,
+    nutriment_diffusion: f32,
+    colormap: u32,
+    tone_mapping: u32,
+    exposure: f32,
+    gamma: f32,
+    white: f32,
+    nutriment_opacity: f32,
+    nutriment_blend: u32,
+    trails_opacity: f32,
+    trails_blend: u32,
+    obstacles_opacity: f32,
+    obstacles_blend: u32,
+    deposit_scale: f32,
 };
 
-struct Color {
-    r: f32,
-    g: f32,
-    b: f32
-}
+// Parameters of one species, array size is MAX_SPECIES
+struct Species {
+    v: f32,
+    deposit: f32,
+    repellent: f32,
+    phi_sens: f32,
+    turn_speed: f32,
+    w_nutriment: f32,
+    color: Color,
+    interaction: array<f32, 8>,
+    repulsion: array<f32, 8>,
+};
 
 @group(0) @binding(0) var<storage, read_write> agents: array<Agent>;
 @group(0) @binding(1) var<storage, read> slime_in: array<f32>;
-@group(0) @binding(2) var<storage, read_write> slime_out: array<f32>;
+// f32 bits or fixed point depending on uniforms.deposit_mode
+@group(0) @binding(2) var<storage, read_write> deposit: array<atomic<u32>>;
 @group(0) @binding(3) var<storage, read> nutriment: array<f32>;
 @group(0) @binding(4) var<uniform> uniforms: Uniforms;
-@group(0) @binding(5) var<storage, read_write> agent_color: array<Color>;
+// 1 for walls
+@group(0) @binding(5) var<storage, read> obstacles: array<u32>;
 @group(0) @binding(6) var<storage, read_write> slime_color: array<Color>;
 @group(0) @binding(7) var<storage, read> nutriment_color: array<Color>;
+@group(0) @binding(8) var<storage, read> species: array<Species>;
+// agents passing each cell in this step, cleared by the nutriment pass
+@group(0) @binding(9) var<storage, read_write> visits: array<atomic<u32>>;
 
 
+// uniforms.boundary
+let BOUNDARY_WRAP: u32 = 1u;
+let BOUNDARY_REFLECT: u32 = 2u;
+let BOUNDARY_RESPAWN: u32 = 3u;
+let BOUNDARY_ABSORB: u32 = 4u;
+// x coordinate of agents removed by the absorbing boundary
+let ABSORBED: f32 = -1e30;
+// Positions drawn for a respawn before staying in place
+let RESPAWN_TRIES: u32 = 16u;
+
 // Hash function www.cs.ubc.ca/~rbridson/docs/schechter-sca08-turbulence.pdf
 fn hash(state: u32) -> u32
 {
@@ -58,20 +105,92 @@ fn rng(seed: u32) -> f32
     return scaleToRange01(hash(seed));
 }
 
-fn sense(phi: f32, a_x: f32, a_y: f32, max_x: f32, max_y: f32)
+// Position on the torus, rounding can end up exactly on the far edge
+fn wrap(s: f32, size: f32) -> f32
+{
+    let w = s - floor(s / size) * size;
+    if (w < 0. || w >= size) {
+        return 0.;
+    }
+    return w;
+}
+
+// Position mirrored at the edges
+fn mirror(s: f32, size: f32) -> f32
+{
+    var r = s;
+    if (s < 0.) {
+        r = -s;
+    } else if (s >= size) {
+        r = 2.*size - s;
+    }
+    if (r >= size) {
+        return size - 1.;
+    }
+    return max(r, 0.);
+}
+
+// Cell mirrored at the edges, -1 is cell 0 and size is cell size-1
+fn mirror_cell(s: f32, size: f32) -> f32
+{
+    let m = wrap(s, 2.*size);
+    if (m >= size) {
+        return 2.*size - 1. - m;
+    }
+    return m;
+}
+
+fn deposit_into(index: u32, amount: f32)
+{
+    if (uniforms.deposit_mode == 1u) {
+        atomicAdd(&deposit[index],
+                  u32(amount * uniforms.deposit_scale + 0.5));
+    } else {  // not atomic, deposits of other agents can get lost
+        let d = bitcast<f32>(atomicLoad(&deposit[index]));
+        atomicStore(&deposit[index], bitcast<u32>(d + amount));
+    }
+}
+
+fn sense(phi: f32, a_x: f32, a_y: f32, max_x: f32, max_y: f32, s: u32)
 -> f32
 {
+    let cells = uniforms.sizeX * uniforms.sizeY;
     var c = 0.;
 
     for(var r = uniforms.sens_range_min; r <= uniforms.sens_range_max; r=r+1.)
     {
-        let s_x = floor(cos(phi)*r + a_x);
-        let s_y = floor(sin(phi)*r + a_y);
+        var s_x = floor(cos(phi)*r + a_x);
+        var s_y = floor(sin(phi)*r + a_y);
 
-        if (s_x < 0. || s_x >= max_x ||s_y < 0. || s_y >= max_y) {break;}
+        if (s_x < 0. || s_x >= max_x || s_y < 0. || s_y >= max_y) {
+            if (uniforms.boundary == BOUNDARY_WRAP) {
+                s_x = wrap(s_x, max_x);
+                s_y = wrap(s_y, max_y);
+            } else if (uniforms.boundary == BOUNDARY_REFLECT) {
+                s_x = mirror_cell(s_x, max_x);
+                s_y = mirror_cell(s_y, max_y);
+            } else {  // nothing to sense outside
+                break;
+            }
+        }
 
         let s_i = u32(s_x) + u32(max_x*s_y);
-        c = c + slime_in[s_i] + uniforms.w_nutriment*nutriment[s_i];
+        if (obstacles[s_i] != 0u) {  // can't sense through walls
+            break;
+        }
+        // weighted trails and repellent trails of all species
+        let n = uniforms.n_species;
+        var trail = 0.;
+        for (var t = 0u; t < n; t = t + 1u) {
+            trail = trail + species[s].interaction[t] * slime_in[t*cells + s_i];
+        }
+        if (uniforms.n_channels > n) {
+            for (var t = 0u; t < n; t = t + 1u) {
+                trail = trail +
+                        species[s].repulsion[t] * slime_in[(n + t)*cells + s_i];
+            }
+        }
+        c = c + trail + species[s].w_nutriment*nutriment[s_i];
     }
 
     return c / uniforms.sense_steps;
@@ -80,84 +199,136 @@ fn sense(phi: f32, a_x: f32, a_y: f32, max_x: f32, max_y: f32)
 @compute
 @workgroup_size(256)
 fn main(@builtin(global_invocation_id) gId: vec3<u32>,
-        @builtin(local_invocation_index) lIdx: u32)
+        @builtin(num_workgroups) nWg: vec3<u32>)
 {
+    // One invocation per agent, rows of workgroups for large counts
+    let i = gId.x + gId.y * nWg.x * 256u;
+    if (i >= uniforms.nAgents || agents[i].x == ABSORBED) {
+        return;
+    }
+
     let pi2 = 3.14159*2.;
     let max_x = f32(uniforms.sizeX);
     let max_y = f32(uniforms.sizeY);
-    let len = u32(f32(uniforms.nAgents) / 256.);
-    let i0 = gId.x * len;
 
-    var phi_max: f32;  // orientation at max. concentration
-    var phi_sens: f32;  // Current sensor orientation
-    var c: f32;
-    var c_max: f32;  // max. concentration
+    // Detect max. slime concentration
+    // It's unlikely that there are often multiple cells
+    // with exact the same concentration -> first detection is selected
+    let a_seed = uniforms.seed + i;
+    let s = agents[i].species;
 
-    for (var i=i0; i<i0+len; i=i+u32(1)){
-        if(i > uniforms.nAgents) {
-            break;
+    let c_left = sense(agents[i].phi - species[s].phi_sens,
+                       agents[i].x, agents[i].y, max_x, max_y, s);
+    let c_center = sense(agents[i].phi,
+                         agents[i].x, agents[i].y, max_x, max_y, s);
+    let c_right = sense(agents[i].phi + species[s].phi_sens,
+                        agents[i].x, agents[i].y, max_x, max_y, s);
+
+    if (c_left > c_center || c_right > c_center) {  // Turn
+        if (c_left == c_right) {
+            agents[i].phi = agents[i].phi +
+                            species[s].turn_speed * 0.5 * rng(a_seed);
+        } else if (c_left > c_right) {
+            agents[i].phi = agents[i].phi - species[s].turn_speed;
+        } else {
+            agents[i].phi = agents[i].phi + species[s].turn_speed;
         }
+    }
+
+    // limit phi between -360 and 360
+    if (agents[i].phi < -pi2) {
+        agents[i].phi = agents[i].phi + pi2;
+    } else { if(agents[i].phi > pi2) {
+        agents[i].phi = agents[i].phi - pi2;
+    }}
+
+    let old_x = agents[i].x;
+    let old_y = agents[i].y;
+    agents[i].x = agents[i].x + cos(agents[i].phi) * species[s].v;
+    agents[i].y = agents[i].y + sin(agents[i].phi) * species[s].v;
 
-        // Detect max. slime concentration
-        // It's unlikely that there are often multiple cells
-        // with exact the same concentration -> first detection is selected
-        phi_max = 0.;  // init phi_max for a no slime case
-        c_max = 0.;
-
-        let a_seed = uniforms.seed + lIdx;
-
-        let c_left = sense(agents[i].phi - uniforms.phi_sens,
-            	           agents[i].x, agents[i].y, max_x, max_y);
-        let c_center = sense(agents[i].phi,
-                             agents[i].x, agents[i].y, max_x, max_y);
-        let c_right = sense(agents[i].phi + uniforms.phi_sens,
-                            agents[i].x, agents[i].y, max_x, max_y);
-
-        if (c_left > c_center || c_right > c_center) {  // Turn
-            if (c_left == c_right) {
-                agents[i].phi = agents[i].phi +
-                                uniforms.turn_speed * 0.5 * rng(a_seed);
-            } else if (c_left > c_right) {
-                agents[i].phi = agents[i].phi - uniforms.turn_speed;
-            } else {
-                agents[i].phi = agents[i].phi + uniforms.turn_speed;
+    var deposit_here = true;
+    if ((agents[i].x < 0.) || (agents[i].y < 0.) ||
+        (agents[i].x >= max_x) || (agents[i].y >= max_y)) {
+        if (uniforms.boundary == BOUNDARY_WRAP) {
+            agents[i].x = wrap(agents[i].x, max_x);
+            agents[i].y = wrap(agents[i].y, max_y);
+        } else if (uniforms.boundary == BOUNDARY_REFLECT) {
+            if (agents[i].x < 0. || agents[i].x >= max_x) {
+                agents[i].x = mirror(agents[i].x, max_x);
+                agents[i].phi = pi2*0.5 - agents[i].phi;
             }
+            if (agents[i].y < 0. || agents[i].y >= max_y) {
+                agents[i].y = mirror(agents[i].y, max_y);
+                agents[i].phi = -agents[i].phi;
+            }
+        } else if (uniforms.boundary == BOUNDARY_RESPAWN) {
+            // the exit position varies the seed between respawns,
+            // positions in walls are drawn again
+            let random = hash(a_seed ^ bitcast<u32>(agents[i].x)) ^
+                         bitcast<u32>(agents[i].y);
+            for (var k = 0u; k < RESPAWN_TRIES; k = k + 1u) {
+                let r = random + 3u * k;
+                agents[i].x = min(rng(r) * max_x, max_x - 1.);
+                agents[i].y = min(rng(r + 1u) * max_y, max_y - 1.);
+                agents[i].phi = rng(r + 2u) * pi2;
+                let cell = u32(floor(agents[i].x)) +
+                           u32(floor(agents[i].y)) * uniforms.sizeX;
+                if (obstacles[cell] == 0u) {
+                    break;
+                }
+            }
+            deposit_here = false;
+        } else if (uniforms.boundary == BOUNDARY_ABSORB) {
+            agents[i].x = ABSORBED;
+            return;
+        } else {
+            let mx = f32(uniforms.sizeX) - 1.;
+            let my = f32(uniforms.sizeY) - 1.;
+            let random = hash(a_seed);
+            agents[i].x = min(mx, max(0., agents[i].x));
+            agents[i].y = min(my, max(0., agents[i].y));
+            agents[i].phi = rng(random) * pi2;
+            deposit_here = false;  // don't set trail on border
         }
-
-        // limit phi between -360 and 360
-        if (agents[i].phi < -pi2) {
-            agents[i].phi = agents[i].phi + pi2;
-        } else { if(agents[i].phi > pi2) {
-            agents[i].phi = agents[i].phi - pi2;
-        }}
-
-        agents[i].x = agents[i].x + cos(agents[i].phi) * uniforms.v;
-        agents[i].y = agents[i].y + sin(agents[i].phi) * uniforms.v;
-
-        if ((agents[i].x < 0.) || (agents[i].y < 0.) ||
-            (agents[i].x >= max_x) || (agents[i].y >= max_y)) {
-                let mx = f32(uniforms.sizeX) - 1.;
-                let my = f32(uniforms.sizeY) - 1.;
-                let random = hash(a_seed);
-                agents[i].x = min(mx, max(0., agents[i].x));
-                agents[i].y = min(my, max(0., agents[i].y));
-                agents[i].phi = rng(random) * pi2;
-        } else {  // don't set trail on border
-            let index: u32 = u32(floor(agents[i].x)) +
-                             u32(floor(agents[i].y)) * uniforms.sizeX;
-            slime_out[index] = slime_out[index] + uniforms.deposit;
+    }
+    // Walls can't be entered, also not by clamping or respawning: turn
+    // around on the free cell the agent came from
+    if (old_x >= 0. && old_y >= 0. && old_x < max_x && old_y < max_y) {
+        let new_cell = u32(floor(agents[i].x)) +
+                 u32(floor(agents[i].y)) * uniforms.sizeX;
+        if (obstacles[new_cell] != 0u) {
+            agents[i].x = old_x;
+            agents[i].y = old_y;
+            agents[i].phi = agents[i].phi + pi2*0.5;
+            deposit_here = false;
         }
-        // Update colors
+    }
+    if (deposit_here) {
+        // trail channel of the species
+        let cells = uniforms.sizeX * uniforms.sizeY;
         let index: u32 = u32(floor(agents[i].x)) +
-                         u32(floor(agents[i].y)) * uniforms.sizeX;
-        if (nutriment[index] > 0.) {
-            agent_color[i] = nutriment_color[index];
+                         u32(floor(agents[i].y)) * uniforms.sizeX +
+                         s * cells;
+        deposit_into(index, species[s].deposit);
+        atomicAdd(&visits[index - s * cells], 1u);
+        if (uniforms.n_channels > uniforms.n_species &&
+            species[s].repellent > 0.) {
+            deposit_into(index + uniforms.n_species * cells,
+                         species[s].repellent);
         }
-        slime_color[index].r = 0.999 * slime_color[index].r +
-                               0.001 * agent_color[i].r;
-        slime_color[index].g = 0.999 * slime_color[index].g +
-                               0.001 * agent_color[i].g;
-        slime_color[index].b = 0.999 * slime_color[index].b +
-                               0.001 * agent_color[i].b;
     }
-}
\ No newline at end of file
+    // Update colors
+    let index: u32 = u32(floor(agents[i].x)) +
+                     u32(floor(agents[i].y)) * uniforms.sizeX;
+    if (uniforms.color_sampling == 1u || nutriment[index] > 0.) {
+        agents[i].color = nutriment_color[index];
+    }
+    let blend = uniforms.color_blend;
+    slime_color[index].r = (1. - blend) * slime_color[index].r +
+                           blend * agents[i].color.r;
+    slime_color[index].g = (1. - blend) * slime_color[index].g +
+                           blend * agents[i].color.g;
+    slime_color[index].b = (1. - blend) * slime_color[index].b +
+                           blend * agents[i].color.b;
+}
@@ -2,48 +2,107 @@ struct Uniforms {
     nAgents: u32,
     sizeX: u32,
     sizeY: u32,
-    deposit: f32,
+    n_species: u32,
+    n_channels: u32,
     decay: f32,
-    v: f32,
-    phi_sens: f32,
-    turn_speed: f32,
+    repellent_decay: f32,
     sens_range_min: f32,
     sens_range_max: f32,
     sense_steps: f32,
-    w_nutriment: f32,
     seed: u32,
+    deposit_mode: u32,
+    boundary: u32,
+    color_sampling: u32,
+    color_blend: f32,
+    consumption: f32,
+    regrowth: f32,
+    nutriment_diffusion: f32,
+    colormap: u32,
+    tone_mapping: u32,
+    exposure: f32,
+    gamma: f32,
+    white: f32,
+    nutriment_opacity: f32,
+    nutriment_blend: u32,
+    trails_opacity: f32,
+    trails_blend: u32,
+    obstacles_opacity: f32,
+    obstacles_blend: u32,
+    deposit_scale: f32,
 };
 
 @group(0) @binding(0) var<storage, read> slime_in: array<f32>;
 @group(0) @binding(1) var<storage, read_write> slime_out: array<f32>;
 @group(0) @binding(2) var<uniform> uniforms: Uniforms;
+@group(0) @binding(3) var<storage, read> deposit: array<u32>;
+@group(0) @binding(4) var<storage, read_write> deposit_next: array<u32>;
+// 1 for walls
+@group(0) @binding(5) var<storage, read> obstacles: array<u32>;
+
+// uniforms.boundary
+let BOUNDARY_WRAP: u32 = 1u;
+let BOUNDARY_REFLECT: u32 = 2u;
+
+fn deposit_at(idx: u32) -> f32
+{
+    if (uniforms.deposit_mode == 1u) {
+        return f32(deposit[idx]) / uniforms.deposit_scale;
+    }
+    return bitcast<f32>(deposit[idx]);
+}
 
 @compute
 @workgroup_size(256)
-fn main(@builtin(global_invocation_id) gId: vec3<u32>)
+fn main(@builtin(global_invocation_id) gId: vec3<u32>,
+        @builtin(num_workgroups) nWg: vec3<u32>)
 {
-    let len = u32(f32(uniforms.sizeX * uniforms.sizeY) / 256.);
-    let i0 = gId.x * len;
+    // One invocation per cell of each trail channel,
+    // rows of workgroups for large grids
+    let cells = uniforms.sizeX * uniforms.sizeY;
+    let i = gId.x + gId.y * nWg.x * 256u;
+    if (i >= cells * uniforms.n_channels) {
+        return;
+    }
 
-    for (var i=i0; i<i0+len; i=i+u32(1)){
-        // get slime pixel coordinates
-        var y0: u32 = u32(floor(f32(i) / f32(uniforms.sizeX)));
-        var x0: u32 = i - y0*uniforms.sizeX;
-        // reset slime out value
-        slime_out[i] = 0.;
-        // sum up 3x3 neighbours
-        for (var dx=-1; dx<2; dx=dx+1){
-            for(var dy=-1; dy<2; dy=dy+1){
-                var x = i32(x0) + dx;
-                var y = i32(y0) + dy;
-                if (x >= 0 || u32(x) < uniforms.sizeX ||
-                    y >= 0 || u32(y) < uniforms.sizeY)
-                {
-                    let idx = u32(x) + u32(y)*uniforms.sizeX;
-                    slime_out[i] = slime_out[i] + slime_in[idx];
-                }
-        }}
-        // calculate mean and decay
-        slime_out[i] = min(slime_out[i] / 9. * uniforms.decay, 1.);
+    // get slime pixel coordinates and the start of the channel
+    let cell = i % cells;
+    let channel = i - cell;
+    var y0: u32 = cell / uniforms.sizeX;
+    var x0: u32 = cell - y0*uniforms.sizeX;
+    // deposit buffer of the next step
+    deposit_next[i] = 0u;
+    // reset slime out value, nothing spreads into walls
+    slime_out[i] = 0.;
+    if (obstacles[cell] != 0u) {
+        return;
     }
+    // sum up 3x3 neighbours
+    for (var dx=-1; dx<2; dx=dx+1){
+        for(var dy=-1; dy<2; dy=dy+1){
+            let size_x = i32(uniforms.sizeX);
+            let size_y = i32(uniforms.sizeY);
+            var x = i32(x0) + dx;
+            var y = i32(y0) + dy;
+            if (x < 0 || x >= size_x || y < 0 || y >= size_y) {
+                if (uniforms.boundary == BOUNDARY_WRAP) {
+                    x = (x + size_x) % size_x;
+                    y = (y + size_y) % size_y;
+                } else if (uniforms.boundary == BOUNDARY_REFLECT) {
+                    x = clamp(x, 0, size_x - 1);
+                    y = clamp(y, 0, size_y - 1);
+                } else {  // outside is empty
+                    continue;
+                }
+            }
+            let n_idx = u32(x) + u32(y)*uniforms.sizeX;
+            if (obstacles[n_idx] != 0u) {
+                continue;
+            }
+            let idx = channel + n_idx;
+            slime_out[i] = slime_out[i] + slime_in[idx] + deposit_at(idx);
+    }}
+    // calculate mean and decay, repellent trails have their own rate
+    let decay = select(uniforms.decay, uniforms.repellent_decay,
+                       i >= uniforms.n_species * cells);
+    slime_out[i] = slime_out[i] / 9. * decay;
 }
\ No newline at end of file
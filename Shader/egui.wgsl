@@ -0,0 +1,61 @@
+// Textured, vertex coloured triangles of the egui parameter panel
+
+struct Screen {
+    size: vec2<f32>,  // in points
+    srgb: u32,  // 1 if the target encodes sRGB itself
+};
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+@group(0) @binding(0) var<uniform> screen: Screen;
+@group(1) @binding(0) var panel_texture: texture_2d<f32>;
+@group(1) @binding(1) var panel_sampler: sampler;
+
+fn linear_from_srgb(c: f32) -> f32
+{
+    if (c < 0.04045) {
+        return c / 12.92;
+    }
+    return pow((c + 0.055) / 1.055, 2.4);
+}
+
+fn srgb_from_linear(c: f32) -> f32
+{
+    if (c < 0.0031308) {
+        return c * 12.92;
+    }
+    return 1.055 * pow(c, 1. / 2.4) - 0.055;
+}
+
+@vertex
+fn vs_main(@location(0) pos: vec2<f32>,
+           @location(1) uv: vec2<f32>,
+           @location(2) color: vec4<f32>) -> VertexOutput
+{
+    var out: VertexOutput;
+    // points with the origin top left to clip space
+    out.position = vec4<f32>(2. * pos.x / screen.size.x - 1.,
+                             1. - 2. * pos.y / screen.size.y,
+                             0., 1.);
+    out.uv = uv;
+    out.color = vec4<f32>(linear_from_srgb(color.r),
+                          linear_from_srgb(color.g),
+                          linear_from_srgb(color.b),
+                          color.a);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32>
+{
+    let c = in.color * textureSample(panel_texture, panel_sampler, in.uv);
+    if (screen.srgb == 1u) {
+        return c;
+    }
+    return vec4<f32>(srgb_from_linear(c.r), srgb_from_linear(c.g),
+                     srgb_from_linear(c.b), c.a);
+}
@@ -0,0 +1,114 @@
+// Post processing of the rendered trails: bloom from a separable
+// gaussian blur of the bright parts, vignette and film grain
+
+struct Post {
+    bloom: f32,  // strength of the glow, 0: no blur passes
+    threshold: f32,  // luminance that starts to glow
+    radius: f32,  // standard deviation of the blur in pixels
+    vignette: f32,
+    grain: f32,
+    frame: u32,  // seed of the grain
+    wrap: u32,  // 1: the blur continues at the opposite edge
+    _padding: u32,
+};
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@group(0) @binding(0) var<uniform> post: Post;
+// scene for the first blur and the composite, half blurred glow else
+@group(0) @binding(1) var source: texture_2d<f32>;
+@group(0) @binding(2) var glow: texture_2d<f32>;
+
+fn hash(state: u32) -> u32
+{
+    var out = state ^ 2747636419u;
+    out = out * 2654435769u;
+    out ^= (out >> u32(16));
+    out = out * 2654435769u;
+    out ^= (out >> u32(16));
+    out = out * 2654435769u;
+    return out;
+}
+
+fn luminance(c: vec3<f32>) -> f32
+{
+    return dot(c, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+// One triangle covering the target, uv with the origin top left
+@vertex
+fn vs_main(@builtin(vertex_index) i: u32) -> VertexOutput
+{
+    var out: VertexOutput;
+    out.uv = vec2<f32>(f32((i << 1u) & 2u), f32(i & 2u));
+    out.position = vec4<f32>(out.uv.x * 2. - 1., 1. - out.uv.y * 2., 0., 1.);
+    return out;
+}
+
+// Gaussian along direction of the source, bright: only above threshold
+fn blur(position: vec4<f32>, direction: vec2<i32>, bright: bool)
+-> vec4<f32>
+{
+    let size = vec2<i32>(textureDimensions(source));
+    let p = vec2<i32>(position.xy);
+    let taps = i32(ceil(3. * post.radius));
+    // multiple of the size keeping wrapped taps positive, the remainder of
+    // negative values differs between the backends
+    let shift = size * (taps / size + 1);
+    var sum = vec3<f32>(0.);
+    var weights = 0.;
+    for (var k = -taps; k <= taps; k = k + 1) {
+        var q = p + k * direction;
+        if (post.wrap == 1u) {
+            q = (q + shift) % size;
+        } else {
+            q = clamp(q, vec2<i32>(0), size - 1);
+        }
+        var c = textureLoad(source, q, 0).rgb;
+        if (bright) {
+            let l = luminance(c);
+            c = c * max(l - post.threshold, 0.) / max(l, 1e-6);
+        }
+        let w = exp(-0.5 * f32(k * k) / (post.radius * post.radius));
+        sum = sum + c * w;
+        weights = weights + w;
+    }
+    return vec4<f32>(sum / weights, 1.);
+}
+
+@fragment
+fn blur_h(in: VertexOutput) -> @location(0) vec4<f32>
+{
+    return blur(in.position, vec2<i32>(1, 0), true);
+}
+
+@fragment
+fn blur_v(in: VertexOutput) -> @location(0) vec4<f32>
+{
+    return blur(in.position, vec2<i32>(0, 1), false);
+}
+
+@fragment
+fn composite(in: VertexOutput) -> @location(0) vec4<f32>
+{
+    let size = vec2<i32>(textureDimensions(source));
+    let p = clamp(vec2<i32>(in.uv * vec2<f32>(size)), vec2<i32>(0), size - 1);
+    var c = textureLoad(source, p, 0).rgb;
+    if (post.bloom > 0.) {
+        c = c + post.bloom * textureLoad(glow, p, 0).rgb;
+    }
+
+    // darker towards the corners, 1 at the corners
+    let d = distance(in.uv, vec2<f32>(0.5)) * 1.41421;
+    c = c * (1. - post.vignette * d * d);
+
+    // zero mean noise, new for every frame
+    let cell = u32(p.x) + u32(p.y) * u32(size.x);
+    let n = f32(hash(cell ^ hash(post.frame))) / 4294967295. - 0.5;
+    c = c * (1. + post.grain * n);
+
+    return vec4<f32>(max(c, vec3<f32>(0.)), 1.);
+}
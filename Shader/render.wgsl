@@ -2,16 +2,33 @@ struct Uniforms {
     nAgents: u32,
     sizeX: u32,
     sizeY: u32,
-    deposit: f32,
+    n_species: u32,
+    n_channels: u32,
     decay: f32,
-    v: f32,
-    phi_sens: f32,
-    turn_speed: f32,
+    repellent_decay: f32,
     sens_range_min: f32,
     sens_range_max: f32,
     sense_steps: f32,
-    w_nutriment: f32,
     seed: u32,
+    deposit_mode: u32,
+    boundary: u32,
+    color_sampling: u32,
+    color_blend: f32,
+    consumption: f32,
+    regrowth: f32,
+    nutriment_diffusion: f32,
+    colormap: u32,
+    tone_mapping: u32,
+    exposure: f32,
+    gamma: f32,
+    white: f32,
+    nutriment_opacity: f32,
+    nutriment_blend: u32,
+    trails_opacity: f32,
+    trails_blend: u32,
+    obstacles_opacity: f32,
+    obstacles_blend: u32,
+    deposit_scale: f32,
 };
 
 struct Color {
@@ -20,9 +37,78 @@ struct Color {
     b: f32
 }
 
+// Parameters of one species, array size is MAX_SPECIES
+struct Species {
+    v: f32,
+    deposit: f32,
+    repellent: f32,
+    phi_sens: f32,
+    turn_speed: f32,
+    w_nutriment: f32,
+    color: Color,
+    interaction: array<f32, 8>,
+    repulsion: array<f32, 8>,
+};
+
 @group(0) @binding(0) var<storage, read> slime: array<f32>;
 @group(0) @binding(1) var<uniform> uniforms: Uniforms;
 @group(0) @binding(2) var<storage, read> slime_color: array<Color>;
+@group(0) @binding(3) var<storage, read> species: array<Species>;
+// Colour map from trail intensity 0 to 1
+@group(0) @binding(4) var<storage, read> colormap: array<Color>;
+@group(0) @binding(5) var<storage, read> nutriment: array<f32>;
+@group(0) @binding(6) var<storage, read> nutriment_color: array<Color>;
+@group(0) @binding(7) var<storage, read> obstacles: array<u32>;
+
+// Colour of the intensity 0..1, interpolated between the table entries
+fn map_intensity(intensity: f32) -> vec3<f32> {
+    let last = arrayLength(&colormap) - 1u;
+    let position = intensity * f32(last);
+    let i = min(u32(position), last - 1u);
+    let low = colormap[i];
+    let high = colormap[i + 1u];
+    return mix(vec3<f32>(low.r, low.g, low.b),
+               vec3<f32>(high.r, high.g, high.b),
+               position - f32(i));
+}
+
+let TONE_LOG: u32 = 1u;
+let TONE_REINHARD: u32 = 2u;
+let TONE_ACES: u32 = 3u;
+
+// Exposed trail to display intensity 0..1, brightened by gamma
+fn tone_map(trail: vec3<f32>) -> vec3<f32> {
+    let x = max(trail * uniforms.exposure, vec3<f32>(0.));
+    let white = uniforms.white;
+    var mapped = x;
+    if (uniforms.tone_mapping == TONE_LOG) {
+        mapped = log(vec3<f32>(1.) + x) / log(1. + white);
+    } else if (uniforms.tone_mapping == TONE_REINHARD) {
+        mapped = x * (vec3<f32>(1.) + x / (white * white)) /
+                 (vec3<f32>(1.) + x);
+    } else if (uniforms.tone_mapping == TONE_ACES) {
+        // Narkowicz' fit of the ACES filmic curve
+        mapped = x * (2.51 * x + 0.03) / (x * (2.43 * x + 0.59) + 0.14);
+    }
+    mapped = clamp(mapped, vec3<f32>(0.), vec3<f32>(1.));
+    return pow(mapped, vec3<f32>(1. / uniforms.gamma));
+}
+
+let BLEND_SCREEN: u32 = 1u;
+let BLEND_MULTIPLY: u32 = 2u;
+
+// Layer combined with the layers below, opacity 0 keeps them
+fn blend(below: vec3<f32>, layer: vec3<f32>, opacity: f32, mode: u32)
+-> vec3<f32>
+{
+    var blended = below + layer;
+    if (mode == BLEND_SCREEN) {
+        blended = below + layer - below * layer;
+    } else if (mode == BLEND_MULTIPLY) {
+        blended = below * layer;
+    }
+    return mix(below, blended, opacity);
+}
 
 @fragment
 fn main(@location(0) tex_coords: vec2<f32>) -> @location(0) vec4<f32> {
@@ -30,9 +116,30 @@ fn main(@location(0) tex_coords: vec2<f32>) -> @location(0) vec4<f32> {
     let y = u32(tex_coords.y * f32(uniforms.sizeY));
     let index = x + y*uniforms.sizeX;
 
-    return vec4<f32>(slime_color[index].r * slime[index],
-                     slime_color[index].g * slime[index],
-                     slime_color[index].b * slime[index],
-                     1.
-                     );
-}
\ No newline at end of file
+    // trails in the colour of their species or mapped by their sum
+    let cells = uniforms.sizeX * uniforms.sizeY;
+    var trail = vec3<f32>(0.);
+    var intensity = 0.;
+    for (var t = 0u; t < uniforms.n_species; t = t + 1u) {
+        let c = species[t].color;
+        trail = trail + vec3<f32>(c.r, c.g, c.b) * slime[t*cells + index];
+        intensity = intensity + slime[t*cells + index];
+    }
+    if (uniforms.colormap == 1u) {
+        trail = map_intensity(tone_map(vec3<f32>(intensity)).x);
+    } else {
+        trail = tone_map(trail);
+    }
+
+    let painted = slime_color[index];
+    trail = trail * vec3<f32>(painted.r, painted.g, painted.b);
+
+    // layers from the bottom over black
+    let n = nutriment_color[index];
+    var c = blend(vec3<f32>(0.), vec3<f32>(n.r, n.g, n.b) * nutriment[index],
+                  uniforms.nutriment_opacity, uniforms.nutriment_blend);
+    c = blend(c, trail, uniforms.trails_opacity, uniforms.trails_blend);
+    c = blend(c, vec3<f32>(f32(obstacles[index] != 0u)),
+              uniforms.obstacles_opacity, uniforms.obstacles_blend);
+    return vec4<f32>(c, 1.);
+}
@@ -0,0 +1,89 @@
+// Agents drawn as sprites over the other layers, one instance per agent
+// of the agents buffer
+
+struct Sprites {
+    size_x: f32,
+    size_y: f32,
+    diameter: f32,  // in pixels of the grid
+    opacity: f32,  // of each sprite
+    coloring: u32,  // 0: agent colour, 1: heading
+    blend: u32,  // 0: add, 1: screen, 2: multiply
+    wrap: u32,  // 1: four quads per agent, copies across the edges
+    _padding: u32,
+};
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) corner: vec2<f32>,
+    @location(1) color: vec3<f32>,
+};
+
+@group(0) @binding(0) var<uniform> sprites: Sprites;
+
+let ABSORBED: f32 = -1e30;
+let PI2: f32 = 6.28318;
+let BLEND_MULTIPLY: u32 = 2u;
+
+// Fully saturated colour of hue h in 0..1
+fn hue(h: f32) -> vec3<f32>
+{
+    let k = vec3<f32>(1., 2. / 3., 1. / 3.);
+    return clamp(abs(fract(vec3<f32>(h) + k) * 6. - 3.) - 1.,
+                 vec3<f32>(0.), vec3<f32>(1.));
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) v: u32,
+           @location(0) agent: vec3<f32>,  // x, y, phi
+           @location(1) color: vec3<f32>) -> VertexOutput
+{
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1., -1.), vec2<f32>(1., -1.), vec2<f32>(1., 1.),
+        vec2<f32>(-1., -1.), vec2<f32>(1., 1.), vec2<f32>(-1., 1.));
+    var out: VertexOutput;
+    out.corner = corners[v % 6u];
+    // removed agents collapse to an empty triangle
+    if (agent.x == ABSORBED) {
+        out.position = vec4<f32>(-2., -2., 0., 1.);
+        out.color = vec3<f32>(0.);
+        return out;
+    }
+
+    // copy 1 and 3 shifted to the nearer left or right edge, 2 and 3 to the
+    // nearer top or bottom one, they are clipped unless crossing it
+    let size = vec2<f32>(sprites.size_x, sprites.size_y);
+    let copy = v / 6u;
+    var shift = vec2<f32>(0.);
+    if ((copy & 1u) != 0u) {
+        shift.x = select(-size.x, size.x, agent.x < 0.5 * size.x);
+    }
+    if ((copy & 2u) != 0u) {
+        shift.y = select(-size.y, size.y, agent.y < 0.5 * size.y);
+    }
+    let pixel = agent.xy + shift + out.corner * 0.5 * sprites.diameter;
+    out.position = vec4<f32>(2. * pixel.x / sprites.size_x - 1.,
+                             1. - 2. * pixel.y / sprites.size_y,
+                             0., 1.);
+    if (sprites.coloring == 1u) {
+        out.color = hue(fract(agent.z / PI2));
+    } else {
+        out.color = color;
+    }
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32>
+{
+    // soft discs, small sprites cover their pixels fully
+    var falloff = 1.;
+    if (sprites.diameter > 2.) {
+        falloff = max(1. - dot(in.corner, in.corner), 0.);
+    }
+    let coverage = sprites.opacity * falloff;
+    // the pipeline multiplies the colour below with this factor
+    if (sprites.blend == BLEND_MULTIPLY) {
+        return vec4<f32>(vec3<f32>(1. - coverage) + coverage * in.color, 1.);
+    }
+    return vec4<f32>(in.color * coverage, 1.);
+}
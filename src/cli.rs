@@ -0,0 +1,75 @@
+use clap::{Parser, ValueEnum};
+
+
+#[derive(Parser, Debug)]
+#[command(version, about = "Physarum slime mould simulation")]
+pub struct Args {
+    /// Grid width in pixel [default: config value or 1024]
+    #[arg(long)]
+    pub width: Option<u32>,
+
+    /// Grid height in pixel [default: config value or 1024]
+    #[arg(long)]
+    pub height: Option<u32>,
+
+    /// Number of agents [default: config value or 2^22]
+    #[arg(long)]
+    pub agents: Option<u32>,
+
+    /// Configuration file, missing keys are randomised
+    #[arg(long, default_value = "./config.json")]
+    pub config: String,
+
+    /// Graphics backend
+    #[arg(long, value_enum, default_value_t = Backend::All)]
+    pub backend: Backend,
+
+    /// Master seed for all random values [default: config value or random]
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Prefer a software adapter (e.g. lavapipe, llvmpipe)
+    #[arg(long)]
+    pub software: bool,
+
+    /// Run without a window and write PNG frames instead
+    #[arg(long)]
+    pub headless: bool,
+
+    /// Number of simulation steps in headless mode
+    #[arg(long, default_value_t = 1000)]
+    pub steps: u32,
+
+    /// Write a frame every n steps in headless mode [default: last step only]
+    #[arg(long)]
+    pub frame_every: Option<u32>,
+
+    /// Seamless texture: wrap at all edges and check the seams of frames
+    #[arg(long)]
+    pub tileable: bool,
+
+    /// Output directory for headless frames
+    #[arg(long, default_value = "./frames")]
+    pub output: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Backend {
+    Vulkan,
+    Gl,
+    Dx12,
+    Metal,
+    All,
+}
+
+impl Backend {
+    pub fn backends(self) -> wgpu::Backends {
+        match self {
+            Backend::Vulkan => wgpu::Backends::VULKAN,
+            Backend::Gl => wgpu::Backends::GL,
+            Backend::Dx12 => wgpu::Backends::DX12,
+            Backend::Metal => wgpu::Backends::METAL,
+            Backend::All => wgpu::Backends::all(),
+        }
+    }
+}
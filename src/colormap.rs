@@ -0,0 +1,141 @@
+// Colour maps for the trail intensity, resolved into a lookup table
+// that the render pipeline samples.
+use serde::{Deserialize, Serialize};
+
+use crate::config::ConfigError;
+use crate::gpu_create::Color;
+use crate::maps::load_image;
+
+
+// Entries of the lookup table, from intensity 0 to 1
+pub const LUT_SIZE: usize = 256;
+
+// How the trails are coloured
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Colormap {
+    // Every trail in the colour of its species
+    #[default]
+    Species,
+    Magma,
+    Viridis,
+    Inferno,
+    // Colours evenly spaced from intensity 0 to 1, linear like the
+    // species colours
+    Gradient(Vec<[f32; 3]>),
+    // PNG/JPEG strip, the left edge is intensity 0
+    Image(String)
+}
+
+// Polynomial fits of the matplotlib maps, coefficients of t^0 .. t^6
+type Fit = [[f32; 3]; 7];
+
+const MAGMA: Fit = [
+    [-0.00213649, -0.0007497, -0.00538613],
+    [0.251661, 0.677523, 2.49403],
+    [8.35372, -3.57772, 0.314468],
+    [-27.6687, 14.2647, -13.6492],
+    [52.1761, -27.9436, 12.9442],
+    [-50.7685, 29.0466, 4.23415],
+    [18.6557, -11.4898, -5.60196]
+];
+
+const VIRIDIS: Fit = [
+    [0.277727, 0.00540734, 0.3341],
+    [0.105093, 1.40461, 1.38459],
+    [-0.330862, 0.214848, 0.0950952],
+    [-4.63423, -5.7991, -19.3324],
+    [6.22827, 14.1799, 56.6906],
+    [4.77639, -13.7451, -65.353],
+    [-5.43546, 4.64585, 26.3124]
+];
+
+const INFERNO: Fit = [
+    [0.0002189, 0.001651, -0.0194809],
+    [0.106513, 0.563956, 3.93271],
+    [11.6025, -3.97285, -15.9424],
+    [-41.704, 17.4364, 44.3541],
+    [77.1629, -33.4024, -81.8073],
+    [-71.3194, 32.6261, 73.2095],
+    [25.1311, -12.2427, -23.0703]
+];
+
+impl Colormap {
+    // true if the trails are coloured by their species
+    pub fn is_species(&self) -> bool {
+        matches!(self, Colormap::Species)
+    }
+
+    // Lookup table of LUT_SIZE linear colours, white for Species.
+    // Fails if the image can't be read.
+    pub fn lut(&self) -> Result<Vec<Color>, ConfigError> {
+        let t = |i: usize| i as f32 / (LUT_SIZE - 1) as f32;
+        Ok(match self {
+            Colormap::Species => vec![Color {r: 1., g: 1., b: 1.}; LUT_SIZE],
+            Colormap::Magma => (0 .. LUT_SIZE).map(|i| fit(&MAGMA, t(i)))
+                                              .collect(),
+            Colormap::Viridis => (0 .. LUT_SIZE).map(|i| fit(&VIRIDIS, t(i)))
+                                                .collect(),
+            Colormap::Inferno => (0 .. LUT_SIZE).map(|i| fit(&INFERNO, t(i)))
+                                                .collect(),
+            Colormap::Gradient(colors) => (0 .. LUT_SIZE)
+                .map(|i| gradient(colors, t(i)))
+                .collect(),
+            Colormap::Image(path) => load_image(path, LUT_SIZE as u32, 1)?
+                .pixels()
+                .map(|pixel| {
+                    let [r, g, b, _] = pixel.0.map(|c| c as f32 / 255.);
+                    Color {r: linear(r), g: linear(g), b: linear(b)}
+                })
+                .collect()
+        })
+    }
+
+    pub(crate) fn validate(&self, errors: &mut Vec<String>) {
+        if let Colormap::Gradient(colors) = self {
+            if colors.len() < 2 {
+                errors.push("colormap: a gradient needs at least 2 colours"
+                            .to_string());
+            }
+            if colors.iter().flatten()
+                     .any(|c| !c.is_finite() || *c < 0.) {
+                errors.push(format!("colormap: {:?} must be >= 0", colors));
+            }
+        }
+    }
+}
+
+// Linear colour of a fitted sRGB map at t in 0..1
+fn fit(coefficients: &Fit, t: f32) -> Color {
+    let mut rgb = [0.; 3];
+    for (c, value) in rgb.iter_mut().enumerate() {
+        // Horner scheme from the highest power
+        let srgb = coefficients.iter().rev()
+            .fold(0., |sum, coefficient| sum * t + coefficient[c]);
+        *value = linear(srgb.clamp(0., 1.));
+    }
+    Color {r: rgb[0], g: rgb[1], b: rgb[2]}
+}
+
+// Colour between the two neighbouring stops of t in 0..1
+fn gradient(colors: &[[f32; 3]], t: f32) -> Color {
+    if colors.len() < 2 {
+        let [r, g, b] = colors.first().copied().unwrap_or_default();
+        return Color {r, g, b};
+    }
+    let position = t * (colors.len() - 1) as f32;
+    let i = (position as usize).min(colors.len() - 2);
+    let share = position - i as f32;
+    let [r, g, b] = [0, 1, 2].map(
+        |c| colors[i][c] + (colors[i + 1][c] - colors[i][c]) * share);
+    Color {r, g, b}
+}
+
+// sRGB encoded value to linear light
+fn linear(c: f32) -> f32 {
+    if c < 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
@@ -0,0 +1,850 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::{f32::consts::PI, fmt, fs, io};
+
+use crate::colormap::Colormap;
+use crate::gpu_create::deposit_scale;
+use crate::timeline::{at_step, Keyframe};
+
+
+// Defaults for values not set in the configuration
+pub const SIZE_X: u32 = 1024;
+pub const SIZE_Y: u32 = 1024;
+pub const N_AGENTS: u32 = 2_u32.pow(22);
+// Trail channels the shaders are built for
+pub const MAX_SPECIES: usize = 8;
+// Largest blur of the bloom, the taps grow with the radius
+pub const MAX_BLOOM_RADIUS: f32 = 64.;
+// Largest agent sprite in pixels
+pub const MAX_SPRITE_SIZE: f32 = 16.;
+
+// Independent random streams derived from the master seed
+pub const STREAM_CONFIG: u64 = 0;
+pub const STREAM_SHADER: u64 = 1;
+pub const STREAM_AGENTS: u64 = 2;
+pub const STREAM_NUTRIMENT: u64 = 3;
+
+pub fn seeded_rng(seed: u64, stream: u64) -> ChaCha8Rng {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    rng.set_stream(stream);
+    rng
+}
+
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub r_init: f32,  // Radius for agent initialisation
+    pub deposit: f32,  // Slime deposition of each agent per step
+    pub decay: f32,
+    pub v: f32,
+    pub phi_sens: f32,  // Sensor angle
+    pub turn_speed: f32,  // turn speed in rad per step
+    pub sens_range_min: f32,
+    pub sens_range_max: f32,
+    pub n_fix: u32,  // Number of fixed max slime zones
+    pub r_fix_min: u32,  // min radius of fixed max slime zones
+    pub r_fix_max: u32,  // max radius of fixed max slime zones
+    // PNG/JPEG used as nutriment instead of the random zones
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nutriment_image: Option<String>,
+    // PNG/JPEG with walls as bright pixels
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub obstacle_image: Option<String>,
+    // weighting factor of fixed slime in agent sensors, negative repels
+    pub w_nutriment: f32,
+    pub repellent: f32,  // deposition into the repellent trail per step
+    pub repellent_decay: f32,
+    pub deposit_mode: DepositMode,
+    pub boundary: Boundary,  // what happens at the edges of the grid
+    pub color_sampling: ColorSampling,  // when agents take the nutriment colour
+    pub color_blend: f32,  // share of the agent colour in a visited cell
+    // nutriment eaten by each agent passing a cell
+    pub consumption: f32,
+    // share of the eaten nutriment growing back per step
+    pub regrowth: f32,
+    // share of the nutriment exchanged with the 3x3 neighbours per step
+    pub nutriment_diffusion: f32,
+    // colours of the trail intensity, species: the species colours
+    pub colormap: Colormap,
+    // Display of the unlimited trail field: scaled by exposure, mapped
+    // to 0..1 by tone_mapping and brightened by gamma
+    pub exposure: f32,
+    pub tone_mapping: ToneMapping,
+    pub gamma: f32,
+    pub white: f32,  // exposed trail shown as full intensity by log/reinhard
+    // Glow added around trails brighter than bloom_threshold,
+    // blurred with bloom_radius pixels, 0: off
+    pub bloom: f32,
+    pub bloom_threshold: f32,
+    pub bloom_radius: f32,
+    pub vignette: f32,  // darkening of the corners 0..1, off for wrap
+    pub grain: f32,  // strength of the film grain 0..1
+    // Layers composited over black before the post processing,
+    // from the bottom: nutriment, trails, obstacles, agents
+    pub layers: Layers,
+    // Populations with own trails, empty: one species with the values above
+    pub species: Vec<Species>,
+    // Parameter keyframes sorted by step, empty: constant parameters
+    pub timeline: Vec<Keyframe>,
+    // Run setup, filled with the effective values before a run is saved
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size_x: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size_y: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n_agents: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shader_seed: Option<u32>,
+    // Master seed for all random values of a run
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>
+}
+
+// How agents add their deposit to the slime field
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DepositMode {
+    // Plain float addition, concurrent deposits into a cell can get lost
+    #[default]
+    Float,
+    // Atomic fixed point accumulation, exact and deterministic
+    Atomic
+}
+
+// When agents take the nutriment colour of their cell
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorSampling {
+    // On nutriment zones only, the colour is kept afterwards
+    #[default]
+    Nutriment,
+    // Every step, agents paint the nutriment image with their trails
+    Continuous
+}
+
+// Curve mapping the exposed trail field to display intensity 0..1
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ToneMapping {
+    // Linear, everything above 1 is saturated
+    #[default]
+    Clamp,
+    // log(1 + x) scaled to reach 1 at white
+    Log,
+    // Extended Reinhard x (1 + x/white^2) / (1 + x)
+    Reinhard,
+    // Filmic curve fitted to the ACES reference transform
+    Aces
+}
+
+// How a layer is combined with the layers below
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlendMode {
+    #[default]
+    Add,
+    // 1 - (1 - below) (1 - layer), brightens without exceeding 1
+    Screen,
+    // Darkens the layers below by the layer colour
+    Multiply
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Layer {
+    pub visible: bool,
+    pub opacity: f32,
+    pub blend: BlendMode
+}
+
+impl Default for Layer {
+    fn default() -> Self {
+        Layer {
+            visible: true,
+            opacity: 1.,
+            blend: BlendMode::Add
+        }
+    }
+}
+
+impl Layer {
+    // Opacity the layer is blended with, 0 if hidden
+    pub fn shown_opacity(&self) -> f32 {
+        if self.visible { self.opacity } else { 0. }
+    }
+
+    fn hidden(opacity: f32) -> Layer {
+        Layer {visible: false, opacity, ..Layer::default()}
+    }
+}
+
+// Render layers, each shown and blended on its own
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Layers {
+    pub nutriment: Layer,  // strength in the nutriment colour
+    pub trails: Layer,
+    pub obstacles: Layer,  // walls in white
+    // every agent as a sprite, the opacity is per sprite
+    pub agents: Layer,
+    pub agent_coloring: AgentColoring,
+    pub agent_size: f32  // sprite diameter in pixels
+}
+
+impl Default for Layers {
+    fn default() -> Self {
+        Layers {
+            nutriment: Layer::hidden(1.),
+            trails: Layer::default(),
+            obstacles: Layer::hidden(1.),
+            agents: Layer::hidden(0.2),
+            agent_coloring: AgentColoring::Color,
+            agent_size: 1.
+        }
+    }
+}
+
+impl Layers {
+    fn each(&self) -> [(&'static str, &Layer); 4] {
+        [("nutriment", &self.nutriment), ("trails", &self.trails),
+         ("obstacles", &self.obstacles), ("agents", &self.agents)]
+    }
+
+    fn validate(&self, errors: &mut Vec<String>) {
+        for (name, layer) in self.each() {
+            if !(0. ..= 1.).contains(&layer.opacity) {
+                errors.push(format!(
+                    "layers.{}.opacity: {} must be within 0..1",
+                    name, layer.opacity));
+            }
+        }
+        if !(1. ..= MAX_SPRITE_SIZE).contains(&self.agent_size) {
+            errors.push(format!(
+                "layers.agent_size: {} must be within 1..{}",
+                self.agent_size, MAX_SPRITE_SIZE));
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AgentColoring {
+    // Colour taken from the nutriment, white before
+    #[default]
+    Color,
+    // Hue of the direction of movement
+    Heading
+}
+
+// Parameters of one population, unset values are taken from the top level
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Species {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub v: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub phi_sens: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub turn_speed: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deposit: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repellent: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub w_nutriment: Option<f32>,
+    pub color: [f32; 3],  // rgb of the trail
+    // Sensor weight of the trail of each species, negative repels.
+    // Empty: attracted by the own trail (1), repelled by the others (-1)
+    pub interaction: Vec<f32>,
+    // Sensor weight of the repellent trail of each species, empty: all -1
+    pub repulsion: Vec<f32>
+}
+
+impl Default for Species {
+    fn default() -> Self {
+        Species {
+            v: None,
+            phi_sens: None,
+            turn_speed: None,
+            deposit: None,
+            repellent: None,
+            w_nutriment: None,
+            color: [1., 1., 1.],
+            interaction: Vec::new(),
+            repulsion: Vec::new()
+        }
+    }
+}
+
+impl Species {
+    // Sensor weight of the trail of species t
+    pub fn interaction_with(&self, own: usize, t: usize) -> f32 {
+        match self.interaction.get(t) {
+            Some(w) => *w,
+            None if t == own => 1.,
+            None => -1.
+        }
+    }
+
+    // Sensor weight of the repellent trail of species t
+    pub fn repulsion_from(&self, t: usize) -> f32 {
+        self.repulsion.get(t).copied().unwrap_or(-1.)
+    }
+
+    fn validate(&self, i: usize, n: usize, errors: &mut Vec<String>) {
+        let floats = [("v", self.v),
+                      ("phi_sens", self.phi_sens),
+                      ("turn_speed", self.turn_speed),
+                      ("deposit", self.deposit),
+                      ("repellent", self.repellent)];
+        for (name, value) in floats {
+            match value {
+                Some(value) if !value.is_finite() || value < 0. =>
+                    errors.push(format!(
+                        "species[{}].{}: {} must be a number >= 0",
+                        i, name, value)),
+                _ => {}
+            }
+        }
+        if let Some(phi_sens) = self.phi_sens {
+            if phi_sens > PI {
+                errors.push(format!("species[{}].phi_sens: {} must be <= pi",
+                                    i, phi_sens));
+            }
+        }
+        if self.color.iter().any(|c| !c.is_finite() || *c < 0.) {
+            errors.push(format!("species[{}].color: {:?} must be >= 0",
+                                i, self.color));
+        }
+        if let Some(w) = self.w_nutriment {
+            if !w.is_finite() {
+                errors.push(format!(
+                    "species[{}].w_nutriment: {} is not a finite number", i, w));
+            }
+        }
+        let weights = [("interaction", &self.interaction),
+                       ("repulsion", &self.repulsion)];
+        for (name, weights) in weights {
+            if !weights.is_empty() && weights.len() != n {
+                errors.push(format!(
+                    "species[{}].{}: needs one weight per species ({})",
+                    i, name, n));
+            }
+            if weights.iter().any(|w| !w.is_finite()) {
+                errors.push(format!(
+                    "species[{}].{}: {:?} are not all finite numbers",
+                    i, name, weights));
+            }
+        }
+    }
+}
+
+// Edge handling of agent movement, sensing and diffusion
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Boundary {
+    // Stop at the edge with a random new heading, outside reads as empty
+    #[default]
+    Clamp,
+    // Toroidal grid, leaving one edge enters at the opposite one
+    Wrap,
+    // Mirror position and heading at the edge, the field is mirrored too
+    Reflect,
+    // Agents leaving the grid restart at a random position
+    Respawn,
+    // Agents leaving the grid are removed, outside reads as empty
+    Absorb
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+    Image(String, image::ImageError),  // path of the image
+    Invalid(Vec<String>)  // one message per offending field
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "Error open config file: {}", e),
+            ConfigError::Parse(e) => write!(f, "Error reading config: {}", e),
+            ConfigError::Image(path, e) =>
+                write!(f, "Error reading image {:?}: {}", path, e),
+            ConfigError::Invalid(errors) => {
+                writeln!(f, "Invalid configuration:")?;
+                for e in errors {
+                    writeln!(f, "  {}", e)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for ConfigError {
+    fn from(e: serde_json::Error) -> Self {
+        ConfigError::Parse(e)
+    }
+}
+
+// Used for keys missing in a config that is deserialized on its own
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            r_init: 50.,
+            deposit: 0.01,
+            decay: 0.5,
+            v: 1.,
+            phi_sens: 0.25*PI,
+            turn_speed: 0.1*PI,
+            sens_range_min: 1.,
+            sens_range_max: 9.,
+            n_fix: 0,
+            r_fix_min: 1,
+            r_fix_max: 5,
+            nutriment_image: None,
+            obstacle_image: None,
+            w_nutriment: 1.,
+            repellent: 0.,
+            repellent_decay: 0.9,
+            deposit_mode: DepositMode::Float,
+            boundary: Boundary::Clamp,
+            color_sampling: ColorSampling::Nutriment,
+            color_blend: 0.001,
+            consumption: 0.,
+            regrowth: 0.,
+            nutriment_diffusion: 0.,
+            colormap: Colormap::Species,
+            exposure: 1.,
+            tone_mapping: ToneMapping::Clamp,
+            gamma: 1.,
+            white: 4.,
+            bloom: 0.,
+            bloom_threshold: 0.5,
+            bloom_radius: 8.,
+            vignette: 0.,
+            grain: 0.,
+            layers: Layers::default(),
+            species: Vec::new(),
+            timeline: Vec::new(),
+            size_x: None,
+            size_y: None,
+            n_agents: None,
+            shader_seed: None,
+            seed: None
+        }
+    }
+}
+
+// Every key of overrides replaces or is merged into the value of fields,
+// path is the position of fields in the file for warnings
+fn merge_fields(fields: &mut serde_json::Map<String, serde_json::Value>,
+                overrides: serde_json::Map<String, serde_json::Value>,
+                path: &str) {
+    for (key, value) in overrides {
+        let key_path = if path.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", path, key)
+        };
+        match fields.get_mut(&key) {
+            Some(current) => merge_json(current, value, &key_path),
+            None => {
+                // unset top level values aren't serialized
+                if !path.is_empty() {
+                    eprintln!("Warning: unknown configuration key {}",
+                              key_path);
+                }
+                fields.insert(key, value);
+            }
+        }
+    }
+}
+
+// Objects are merged key by key, anything else is replaced. A single key
+// replacing another single key is another enum variant (e.g. colormap
+// gradient -> image) and replaces it as a whole.
+fn merge_json(current: &mut serde_json::Value, value: serde_json::Value,
+              path: &str) {
+    use serde_json::Value::Object;
+    let variant = |fields: &serde_json::Map<_, _>,
+                   overrides: &serde_json::Map<String, _>|
+        fields.len() == 1 && overrides.len() == 1 &&
+        overrides.keys().all(|k| !fields.contains_key(k));
+    match (current, value) {
+        (Object(fields), Object(overrides))
+            if !variant(fields, &overrides) =>
+                merge_fields(fields, overrides, path),
+        (current, value) => *current = value
+    }
+}
+
+impl Config {
+    pub fn new<R: Rng>(rng: &mut R) -> Config {
+        Config {
+            r_init: rng.gen_range(5. .. 100.),
+            deposit: rng.gen_range(0.0001 .. 0.1),
+            decay: rng.gen_range(0.1 .. 0.9),
+            v: rng.gen_range(0.5 .. 25.),
+            phi_sens: rng.gen_range(0.1*PI .. 0.5*PI),
+            turn_speed: rng.gen_range(0.01 .. 0.2*PI),
+            sens_range_min: rng.gen_range(1. .. 5.),
+            sens_range_max: rng.gen_range(5. .. 50.),
+            n_fix: rng.gen_range(0 .. 25),
+            r_fix_min: rng.gen_range(1 .. 2),
+            r_fix_max: rng.gen_range(2 .. 10),
+            nutriment_image: None,
+            obstacle_image: None,
+            w_nutriment: rng.gen_range(0.1 .. 10.),
+            repellent: 0.,
+            repellent_decay: 0.9,
+            deposit_mode: DepositMode::Float,
+            boundary: Boundary::Clamp,
+            color_sampling: ColorSampling::Nutriment,
+            color_blend: 0.001,
+            consumption: 0.,
+            regrowth: 0.,
+            nutriment_diffusion: 0.,
+            colormap: Colormap::Species,
+            exposure: 1.,
+            tone_mapping: ToneMapping::Clamp,
+            gamma: 1.,
+            white: 4.,
+            bloom: 0.,
+            bloom_threshold: 0.5,
+            bloom_radius: 8.,
+            vignette: 0.,
+            grain: 0.,
+            layers: Layers::default(),
+            species: Vec::new(),
+            timeline: Vec::new(),
+            size_x: None,
+            size_y: None,
+            n_agents: None,
+            shader_seed: None,
+            seed: None
+        }
+    }
+
+    // Re-roll the values drawn by new. Maps, species, boundary, rendering
+    // and the run setup stay as they are.
+    pub fn randomise<R: Rng>(&mut self, rng: &mut R) {
+        let random = Config::new(rng);
+        self.r_init = random.r_init;
+        self.deposit = random.deposit;
+        self.decay = random.decay;
+        self.v = random.v;
+        self.phi_sens = random.phi_sens;
+        self.turn_speed = random.turn_speed;
+        self.sens_range_min = random.sens_range_min;
+        self.sens_range_max = random.sens_range_max;
+        self.n_fix = random.n_fix;
+        self.r_fix_min = random.r_fix_min;
+        self.r_fix_max = random.r_fix_max;
+        self.w_nutriment = random.w_nutriment;
+    }
+
+    // Largest amount one agent deposits into a trail channel in a step
+    pub fn max_deposit(&self) -> f32 {
+        let amount = |deposit: f32, repellent: f32| deposit.max(repellent);
+        if self.species.is_empty() {
+            return amount(self.deposit, self.repellent);
+        }
+        self.species.iter()
+            .map(|sp| amount(sp.deposit.unwrap_or(self.deposit),
+                             sp.repellent.unwrap_or(self.repellent)))
+            .fold(0., f32::max)
+    }
+
+    // Override the current values with all keys present in the json file.
+    // Keys missing in the file keep their current (e.g. random) value,
+    // also inside nested objects such as layers.
+    pub fn load_json(&mut self, config_file: &str) -> Result<(), ConfigError> {
+        let overrides = Config::read_json(config_file)?;
+
+        let mut merged = serde_json::to_value(&*self)?;
+        if let serde_json::Value::Object(fields) = &mut merged {
+            merge_fields(fields, overrides, "");
+        }
+        let cfg: Config = serde_json::from_value(merged)?;
+        cfg.validate()?;
+
+        *self = cfg;
+        Ok(())
+    }
+
+    // Fill unset run setup values: default grid and agent count,
+    // random master seed and the shader seed derived from it
+    pub fn complete(&mut self) {
+        self.size_x.get_or_insert(SIZE_X);
+        self.size_y.get_or_insert(SIZE_Y);
+        self.n_agents.get_or_insert(N_AGENTS);
+        let seed = *self.seed.get_or_insert_with(rand::random);
+        self.shader_seed.get_or_insert_with(
+            || seeded_rng(seed, STREAM_SHADER)
+                   .gen_range(1e7 as u32..9e14 as u32));
+    }
+
+    // Raw key/value pairs of a config file
+    pub fn read_json(config_file: &str)
+    -> Result<serde_json::Map<String, serde_json::Value>, ConfigError>
+    {
+        let data = fs::read_to_string(config_file)?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    // Write the configuration in the format read by load_json
+    pub fn save_json(&self, config_file: &str) -> Result<(), ConfigError> {
+        let data = serde_json::to_string_pretty(self)?;
+        fs::write(config_file, data)?;
+        Ok(())
+    }
+
+    // Collect every invalid value instead of stopping at the first one
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut errors = Vec::new();
+
+        let floats = [("r_init", self.r_init),
+                      ("deposit", self.deposit),
+                      ("decay", self.decay),
+                      ("v", self.v),
+                      ("phi_sens", self.phi_sens),
+                      ("turn_speed", self.turn_speed),
+                      ("sens_range_min", self.sens_range_min),
+                      ("sens_range_max", self.sens_range_max),
+                      ("w_nutriment", self.w_nutriment),
+                      ("repellent", self.repellent),
+                      ("repellent_decay", self.repellent_decay),
+                      ("color_blend", self.color_blend),
+                      ("consumption", self.consumption),
+                      ("regrowth", self.regrowth),
+                      ("nutriment_diffusion", self.nutriment_diffusion),
+                      ("exposure", self.exposure),
+                      ("gamma", self.gamma),
+                      ("white", self.white),
+                      ("bloom", self.bloom),
+                      ("bloom_threshold", self.bloom_threshold),
+                      ("bloom_radius", self.bloom_radius),
+                      ("vignette", self.vignette),
+                      ("grain", self.grain),
+                      ("layers.agent_size", self.layers.agent_size)];
+        for (name, value) in floats {
+            if !value.is_finite() {
+                errors.push(format!("{}: {} is not a finite number",
+                                    name, value));
+            }
+        }
+
+        if self.r_init <= 0. {
+            errors.push(format!("r_init: {} must be > 0", self.r_init));
+        }
+        if self.deposit < 0. {
+            errors.push(format!("deposit: {} must be >= 0", self.deposit));
+        }
+        if !(0. ..= 1.).contains(&self.decay) {
+            errors.push(format!("decay: {} must be within 0..1", self.decay));
+        }
+        if !(0. ..= 1.).contains(&self.color_blend) {
+            errors.push(format!("color_blend: {} must be within 0..1",
+                                self.color_blend));
+        }
+        if self.repellent < 0. {
+            errors.push(format!("repellent: {} must be >= 0", self.repellent));
+        }
+        if !(0. ..= 1.).contains(&self.repellent_decay) {
+            errors.push(format!("repellent_decay: {} must be within 0..1",
+                                self.repellent_decay));
+        }
+        if self.consumption < 0. {
+            errors.push(format!("consumption: {} must be >= 0",
+                                self.consumption));
+        }
+        let rates = [("regrowth", self.regrowth),
+                     ("nutriment_diffusion", self.nutriment_diffusion)];
+        for (name, value) in rates {
+            if !(0. ..= 1.).contains(&value) {
+                errors.push(format!("{}: {} must be within 0..1",
+                                    name, value));
+            }
+        }
+        if self.v < 0. {
+            errors.push(format!("v: {} must be >= 0", self.v));
+        }
+        if !(0. ..= PI).contains(&self.phi_sens) {
+            errors.push(format!("phi_sens: {} must be within 0..pi",
+                                self.phi_sens));
+        }
+        if self.turn_speed < 0. {
+            errors.push(format!("turn_speed: {} must be >= 0",
+                                self.turn_speed));
+        }
+        if self.sens_range_min < 0. {
+            errors.push(format!("sens_range_min: {} must be >= 0",
+                                self.sens_range_min));
+        }
+        if self.sens_range_min > self.sens_range_max {
+            errors.push(format!(
+                "sens_range_min: {} must be <= sens_range_max ({})",
+                self.sens_range_min, self.sens_range_max));
+        }
+        if self.r_fix_min == 0 {
+            errors.push("r_fix_min: must be >= 1".to_string());
+        }
+        if self.r_fix_min >= self.r_fix_max {
+            errors.push(format!("r_fix_min: {} must be < r_fix_max ({})",
+                                self.r_fix_min, self.r_fix_max));
+        }
+
+        if self.species.len() > MAX_SPECIES {
+            errors.push(format!("species: {} are more than {}",
+                                self.species.len(), MAX_SPECIES));
+        }
+        for (i, species) in self.species.iter().enumerate() {
+            species.validate(i, self.species.len(), &mut errors);
+        }
+        self.colormap.validate(&mut errors);
+        let display = [("exposure", self.exposure),
+                       ("gamma", self.gamma),
+                       ("white", self.white)];
+        for (name, value) in display {
+            if value <= 0. {
+                errors.push(format!("{}: {} must be > 0", name, value));
+            }
+        }
+        if self.bloom < 0. {
+            errors.push(format!("bloom: {} must be >= 0", self.bloom));
+        }
+        if self.bloom_threshold < 0. {
+            errors.push(format!("bloom_threshold: {} must be >= 0",
+                                self.bloom_threshold));
+        }
+        if !(0.5 ..= MAX_BLOOM_RADIUS).contains(&self.bloom_radius) {
+            errors.push(format!("bloom_radius: {} must be within 0.5..{}",
+                                self.bloom_radius, MAX_BLOOM_RADIUS));
+        }
+        let shares = [("vignette", self.vignette), ("grain", self.grain)];
+        for (name, value) in shares {
+            if !(0. ..= 1.).contains(&value) {
+                errors.push(format!("{}: {} must be within 0..1",
+                                    name, value));
+            }
+        }
+        self.layers.validate(&mut errors);
+
+        // Animated values are checked where a keyframe sets them,
+        // interpolation stays in between
+        let constant_errors = errors.clone();
+        let mut last_step = None;
+        for (i, keyframe) in self.timeline.iter().enumerate() {
+            if last_step.is_some_and(|last| keyframe.step <= last) {
+                errors.push(format!(
+                    "timeline[{}].step: {} must be after the previous keyframe",
+                    i, keyframe.step));
+            }
+            last_step = Some(keyframe.step);
+
+            let mut animated = at_step(self, keyframe.step);
+            animated.timeline.clear();
+            if let Err(ConfigError::Invalid(invalid)) = animated.validate() {
+                errors.extend(invalid.iter()
+                    .filter(|e| !constant_errors.contains(e))
+                    .map(|e| format!("timeline[{}] (step {}): {}",
+                                     i, keyframe.step, e)));
+            }
+        }
+
+        // Every agent depositing into the same cell has to fit the
+        // fixed point cells of atomic deposits
+        let n_agents = self.n_agents.unwrap_or(N_AGENTS);
+        if self.deposit_mode == DepositMode::Atomic &&
+           deposit_scale(n_agents, self.max_deposit()).is_none() {
+            errors.push(format!(
+                "deposit: {} by {} agents overflows the atomic deposits",
+                self.max_deposit(), n_agents));
+        }
+
+        let run_setup = [("size_x", self.size_x),
+                         ("size_y", self.size_y),
+                         ("n_agents", self.n_agents)];
+        for (name, value) in run_setup {
+            if value == Some(0) {
+                errors.push(format!("{}: must be >= 1", name));
+            }
+        }
+
+        // Nutriment zones have to fit into the grid, unused with an image
+        if self.n_fix > 0 && self.nutriment_image.is_none() {
+            let sizes = [("size_x", self.size_x), ("size_y", self.size_y)];
+            for (name, value) in sizes {
+                match value {
+                    Some(size) if size <= 2*self.r_fix_max => errors.push(
+                        format!("{}: {} must be > 2*r_fix_max ({})",
+                                name, size, 2*self.r_fix_max)),
+                    _ => {}
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(errors))
+        }
+    }
+
+    pub fn show_state(&self) {
+        println!("Physarum configuration:\n--");
+        println!("  r_init: {:?}", self.r_init);
+        println!("  deposit: {:?}", self.deposit);
+        println!("  decay: {:?}", self.decay);
+        println!("  v: {:?}", self.v);
+        println!("  phi_sens: {:?}", self.phi_sens);
+        println!("  turn_speed: {:?}", self.turn_speed);
+        println!("  sens_range_min: {:?}", self.sens_range_min);
+        println!("  sens_range_max: {:?}", self.sens_range_max);
+        println!("  n_fix: {:?}", self.n_fix);
+        println!("  r_fix_min: {:?}", self.r_fix_min);
+        println!("  r_fix_max: {:?}", self.r_fix_max);
+        println!("  nutriment_image: {:?}", self.nutriment_image);
+        println!("  obstacle_image: {:?}", self.obstacle_image);
+        println!("  w_nutriment: {:?}", self.w_nutriment);
+        println!("  repellent: {:?}", self.repellent);
+        println!("  repellent_decay: {:?}", self.repellent_decay);
+        println!("  deposit_mode: {:?}", self.deposit_mode);
+        println!("  boundary: {:?}", self.boundary);
+        println!("  color_sampling: {:?}", self.color_sampling);
+        println!("  color_blend: {:?}", self.color_blend);
+        println!("  consumption: {:?}", self.consumption);
+        println!("  regrowth: {:?}", self.regrowth);
+        println!("  nutriment_diffusion: {:?}", self.nutriment_diffusion);
+        println!("  colormap: {:?}", self.colormap);
+        println!("  exposure: {:?}", self.exposure);
+        println!("  tone_mapping: {:?}", self.tone_mapping);
+        println!("  gamma: {:?}", self.gamma);
+        println!("  white: {:?}", self.white);
+        println!("  bloom: {:?}", self.bloom);
+        println!("  bloom_threshold: {:?}", self.bloom_threshold);
+        println!("  bloom_radius: {:?}", self.bloom_radius);
+        println!("  vignette: {:?}", self.vignette);
+        println!("  grain: {:?}", self.grain);
+        println!("  layers: {:?}", self.layers);
+        println!("  species: {:?}", self.species);
+        println!("  timeline: {:?}", self.timeline);
+        println!("  size_x: {:?}", self.size_x);
+        println!("  size_y: {:?}", self.size_y);
+        println!("  n_agents: {:?}", self.n_agents);
+        println!("  shader_seed: {:?}", self.shader_seed);
+        println!("  seed: {:?}", self.seed);
+    }
+}
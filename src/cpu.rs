@@ -0,0 +1,357 @@
+// CPU reference of the Physarum.wgsl agent step, the Slime.wgsl
+// dissipation and the Nutriment.wgsl regrowth, used to verify the shaders.
+use crate::config::Boundary;
+use crate::gpu_create::{Agent, SpeciesParams, Uniforms, ABSORBED};
+
+
+// Same approximation as in the shaders
+#[allow(clippy::approx_constant)]
+const PI2: f32 = 3.14159*2.;
+
+pub struct CpuSimulation {
+    pub uniforms: Uniforms,
+    pub species: Vec<SpeciesParams>,
+    pub agents: Vec<Agent>,
+    pub slime: Vec<f32>,
+    pub nutriment: Vec<f32>,
+    pub capacity: Vec<f32>,  // initial nutriment
+    pub obstacles: Vec<u32>
+}
+
+impl CpuSimulation {
+    // One step as done by Simulation::encode_step
+    pub fn step(&mut self) {
+        let mut deposit = vec![0.; self.slime.len()];
+        let mut visits = vec![0; self.nutriment.len()];
+        step_agents(&self.uniforms, &self.species, &mut self.agents,
+                    &self.slime, &mut deposit, &mut visits, &self.nutriment,
+                    &self.obstacles);
+        let slime_out: Vec<f32> = self.slime.iter().zip(&deposit)
+            .map(|(s, d)| s + d)
+            .collect();
+        self.slime = diffuse(&self.uniforms, &slime_out, &self.obstacles);
+        self.nutriment = regrow(&self.uniforms, &self.nutriment,
+                                &self.capacity, &visits, &self.obstacles);
+    }
+}
+
+// Hash function www.cs.ubc.ca/~rbridson/docs/schechter-sca08-turbulence.pdf
+pub fn hash(state: u32) -> u32 {
+    let mut out = state ^ 2747636419;
+    out = out.wrapping_mul(2654435769);
+    out ^= out >> 16;
+    out = out.wrapping_mul(2654435769);
+    out ^= out >> 16;
+    out.wrapping_mul(2654435769)
+}
+
+pub fn rng(seed: u32) -> f32 {
+    hash(seed) as f32 / 4294967295.0
+}
+
+const WRAP: u32 = Boundary::Wrap as u32;
+const REFLECT: u32 = Boundary::Reflect as u32;
+const RESPAWN: u32 = Boundary::Respawn as u32;
+const ABSORB: u32 = Boundary::Absorb as u32;
+// Same as in Physarum.wgsl
+const RESPAWN_TRIES: u32 = 16;
+
+// Position on the torus, rounding can end up exactly on the far edge
+fn wrap(s: f32, size: f32) -> f32 {
+    let w = s - (s / size).floor() * size;
+    if w < 0. || w >= size { 0. } else { w }
+}
+
+// Position mirrored at the edges
+fn mirror(s: f32, size: f32) -> f32 {
+    let r = if s < 0. {
+        -s
+    } else if s >= size {
+        2.*size - s
+    } else {
+        s
+    };
+    if r >= size { size - 1. } else { r.max(0.) }
+}
+
+// Cell mirrored at the edges, -1 is cell 0 and size is cell size-1
+fn mirror_cell(s: f32, size: f32) -> f32 {
+    let m = wrap(s, 2.*size);
+    if m >= size { 2.*size - 1. - m } else { m }
+}
+
+
+#[allow(clippy::too_many_arguments)]
+fn sense(u: &Uniforms, sp: &SpeciesParams, slime_in: &[f32],
+         nutriment: &[f32], obstacles: &[u32],
+         phi: f32, a_x: f32, a_y: f32) -> f32 {
+    let max_x = u.size_x as f32;
+    let max_y = u.size_y as f32;
+    let cells = (u.size_x * u.size_y) as usize;
+    let mut c = 0.;
+
+    let mut r = u.sens_range_min;
+    while r <= u.sens_range_max {
+        let mut s_x = (phi.cos()*r + a_x).floor();
+        let mut s_y = (phi.sin()*r + a_y).floor();
+
+        if s_x < 0. || s_x >= max_x || s_y < 0. || s_y >= max_y {
+            match u.boundary {
+                WRAP => {
+                    s_x = wrap(s_x, max_x);
+                    s_y = wrap(s_y, max_y);
+                }
+                REFLECT => {
+                    s_x = mirror_cell(s_x, max_x);
+                    s_y = mirror_cell(s_y, max_y);
+                }
+                _ => break  // nothing to sense outside
+            }
+        }
+
+        let s_i = s_x as usize + (max_x*s_y) as usize;
+        if obstacles[s_i] != 0 {  // can't sense through walls
+            break;
+        }
+        let n = u.n_species as usize;
+        let mut trail = 0.;
+        for t in 0 .. n {
+            trail += sp.interaction[t] * slime_in[t*cells + s_i];
+        }
+        if u.n_channels as usize > n {
+            for t in 0 .. n {
+                trail += sp.repulsion[t] * slime_in[(n + t)*cells + s_i];
+            }
+        }
+        c += trail + sp.w_nutriment*nutriment[s_i];
+        r += 1.;
+    }
+
+    c / u.sense_steps
+}
+
+// Atomic deposits are added in fixed point
+fn quantize(u: &Uniforms, deposit: f32) -> f32 {
+    if u.deposit_mode == 1 {
+        (deposit * u.deposit_scale + 0.5).floor() / u.deposit_scale
+    } else {
+        deposit
+    }
+}
+
+// Sense, turn, move and deposit into slime_out,
+// count the agents passing each cell in visits
+#[allow(clippy::too_many_arguments)]
+pub fn step_agents(u: &Uniforms, species: &[SpeciesParams],
+                   agents: &mut [Agent], slime_in: &[f32],
+                   slime_out: &mut [f32], visits: &mut [u32],
+                   nutriment: &[f32], obstacles: &[u32]) {
+    let max_x = u.size_x as f32;
+    let max_y = u.size_y as f32;
+    let cells = (u.size_x * u.size_y) as usize;
+
+    for (i, a) in agents.iter_mut().enumerate() {
+        if a.x == ABSORBED {
+            continue;
+        }
+        let a_seed = u.seed.wrapping_add(i as u32);
+        let sp = &species[a.species as usize];
+
+        let c_left = sense(u, sp, slime_in, nutriment, obstacles,
+                           a.phi - sp.phi_sens, a.x, a.y);
+        let c_center = sense(u, sp, slime_in, nutriment, obstacles,
+                             a.phi, a.x, a.y);
+        let c_right = sense(u, sp, slime_in, nutriment, obstacles,
+                            a.phi + sp.phi_sens, a.x, a.y);
+
+        if c_left > c_center || c_right > c_center {  // Turn
+            if c_left == c_right {
+                a.phi += sp.turn_speed * 0.5 * rng(a_seed);
+            } else if c_left > c_right {
+                a.phi -= sp.turn_speed;
+            } else {
+                a.phi += sp.turn_speed;
+            }
+        }
+
+        // limit phi between -360 and 360
+        if a.phi < -PI2 {
+            a.phi += PI2;
+        } else if a.phi > PI2 {
+            a.phi -= PI2;
+        }
+
+        let (old_x, old_y) = (a.x, a.y);
+        a.x += a.phi.cos() * sp.v;
+        a.y += a.phi.sin() * sp.v;
+
+        let mut deposit_here = true;
+        if a.x < 0. || a.y < 0. || a.x >= max_x || a.y >= max_y {
+            match u.boundary {
+                WRAP => {
+                    a.x = wrap(a.x, max_x);
+                    a.y = wrap(a.y, max_y);
+                }
+                REFLECT => {
+                    if a.x < 0. || a.x >= max_x {
+                        a.x = mirror(a.x, max_x);
+                        a.phi = PI2*0.5 - a.phi;
+                    }
+                    if a.y < 0. || a.y >= max_y {
+                        a.y = mirror(a.y, max_y);
+                        a.phi = -a.phi;
+                    }
+                }
+                RESPAWN => {
+                    let random = hash(a_seed ^ a.x.to_bits()) ^ a.y.to_bits();
+                    for k in 0 .. RESPAWN_TRIES {
+                        let r = random.wrapping_add(3*k);
+                        a.x = (rng(r) * max_x).min(max_x - 1.);
+                        a.y = (rng(r.wrapping_add(1)) * max_y).min(max_y - 1.);
+                        a.phi = rng(r.wrapping_add(2)) * PI2;
+                        let cell = a.x.floor() as usize +
+                                   a.y.floor() as usize * u.size_x as usize;
+                        if obstacles[cell] == 0 {
+                            break;
+                        }
+                    }
+                    deposit_here = false;
+                }
+                ABSORB => {
+                    a.x = ABSORBED;
+                    continue;
+                }
+                _ => {
+                    a.x = (max_x - 1.).min(a.x.max(0.));
+                    a.y = (max_y - 1.).min(a.y.max(0.));
+                    a.phi = rng(hash(a_seed)) * PI2;
+                    deposit_here = false;  // don't set trail on border
+                }
+            }
+        }
+        // Walls can't be entered, also not by clamping or respawning
+        if old_x >= 0. && old_y >= 0. && old_x < max_x && old_y < max_y {
+            let cell = a.x.floor() as usize +
+                       a.y.floor() as usize * u.size_x as usize;
+            if obstacles[cell] != 0 {
+                a.x = old_x;
+                a.y = old_y;
+                a.phi += PI2*0.5;
+                deposit_here = false;
+            }
+        }
+        if deposit_here {
+            let index = a.x.floor() as usize +
+                        a.y.floor() as usize * u.size_x as usize;
+            let channel = a.species as usize * cells;
+            slime_out[channel + index] += quantize(u, sp.deposit);
+            visits[index] += 1;
+            if u.n_channels > u.n_species && sp.repellent > 0. {
+                let repellent = u.n_species as usize * cells + channel;
+                slime_out[repellent + index] += quantize(u, sp.repellent);
+            }
+        }
+    }
+}
+
+// 3x3 mean of the neighbours in each trail channel and decay, unlimited.
+// Repellent trails decay with their own rate.
+// Outside of the grid is empty unless wrapped or mirrored, walls stay empty.
+pub fn diffuse(u: &Uniforms, slime_in: &[f32], obstacles: &[u32])
+-> Vec<f32> {
+    let size_x = u.size_x as i32;
+    let size_y = u.size_y as i32;
+    let cells = (size_x * size_y) as usize;
+    let mut slime_out = vec![0.; slime_in.len()];
+
+    for c in 0 .. u.n_channels {
+        let channel = c as usize * cells;
+        let decay = if c < u.n_species { u.decay } else { u.repellent_decay };
+        for y0 in 0 .. size_y {
+            for x0 in 0 .. size_x {
+                if obstacles[(x0 + y0*size_x) as usize] != 0 {
+                    continue;
+                }
+                let mut sum = 0.;
+                for dx in -1 .. 2 {
+                    for dy in -1 .. 2 {
+                        let mut x = x0 + dx;
+                        let mut y = y0 + dy;
+                        if x < 0 || x >= size_x || y < 0 || y >= size_y {
+                            match u.boundary {
+                                WRAP => {
+                                    x = (x + size_x) % size_x;
+                                    y = (y + size_y) % size_y;
+                                }
+                                REFLECT => {
+                                    x = x.clamp(0, size_x - 1);
+                                    y = y.clamp(0, size_y - 1);
+                                }
+                                _ => continue
+                            }
+                        }
+                        let n_idx = (x + y*size_x) as usize;
+                        if obstacles[n_idx] == 0 {
+                            sum += slime_in[channel + n_idx];
+                        }
+                    }
+                }
+                slime_out[channel + (x0 + y0*size_x) as usize] =
+                    sum / 9. * decay;
+            }
+        }
+    }
+    slime_out
+}
+
+// Nutriment mixed with the 3x3 mean of its neighbours, eaten by each
+// agent passing it and grown back towards the capacity.
+// Walls keep their nutriment.
+pub fn regrow(u: &Uniforms, nutriment: &[f32], capacity: &[f32],
+              visits: &[u32], obstacles: &[u32]) -> Vec<f32> {
+    let size_x = u.size_x as i32;
+    let size_y = u.size_y as i32;
+    let mut nutriment_out = nutriment.to_vec();
+
+    for y0 in 0 .. size_y {
+        for x0 in 0 .. size_x {
+            let i = (x0 + y0*size_x) as usize;
+            if obstacles[i] != 0 {
+                continue;
+            }
+            let mut sum = 0.;
+            let mut n = 0.;
+            for dx in -1 .. 2 {
+                for dy in -1 .. 2 {
+                    let mut x = x0 + dx;
+                    let mut y = y0 + dy;
+                    if x < 0 || x >= size_x || y < 0 || y >= size_y {
+                        match u.boundary {
+                            WRAP => {
+                                x = (x + size_x) % size_x;
+                                y = (y + size_y) % size_y;
+                            }
+                            REFLECT => {
+                                x = x.clamp(0, size_x - 1);
+                                y = y.clamp(0, size_y - 1);
+                            }
+                            _ => continue
+                        }
+                    }
+                    let n_idx = (x + y*size_x) as usize;
+                    if obstacles[n_idx] == 0 {
+                        sum += nutriment[n_idx];
+                        n += 1.;
+                    }
+            }}
+            let d = u.nutriment_diffusion;
+            let mut value = (1. - d) * nutriment[i] + d * sum / n;
+
+            value = (value - u.consumption * visits[i] as f32).max(0.);
+
+            value += u.regrowth * (capacity[i] - value);
+            nutriment_out[i] = value.max(0.);
+        }
+    }
+    nutriment_out
+}
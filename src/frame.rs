@@ -0,0 +1,104 @@
+// Offscreen rendering of frames read back as RGBA pixels
+use std::sync::mpsc;
+
+
+// Render target and the buffer to read it back
+pub struct Frame {
+    texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    buffer: wgpu::Buffer,
+    width: u32,
+    height: u32,
+    padded_bytes_per_row: u32,
+    bgra: bool  // channels to swap for RGBA pixels
+}
+
+impl Frame {
+    // format has to be the one the simulation renders to
+    pub fn new(device: &wgpu::Device, width: u32, height: u32,
+               format: wgpu::TextureFormat) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen Frame"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT |
+                   wgpu::TextureUsages::COPY_SRC
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        // Rows of a texture copy have to be aligned to 256 bytes
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = (width * 4).div_ceil(align) * align;
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Frame Readback"),
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST |
+                   wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            texture,
+            view,
+            buffer,
+            width,
+            height,
+            padded_bytes_per_row,
+            bgra: matches!(format, wgpu::TextureFormat::Bgra8Unorm |
+                                   wgpu::TextureFormat::Bgra8UnormSrgb)
+        }
+    }
+
+    pub fn copy_to_buffer(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.copy_texture_to_buffer(
+            self.texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &self.buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(
+                        self.padded_bytes_per_row),
+                    rows_per_image: None
+                }
+            },
+            wgpu::Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1
+            });
+    }
+
+    // Wait for the copy and return the unpadded RGBA rows
+    pub fn read_pixels(&self, device: &wgpu::Device) -> Vec<u8> {
+        let slice = self.buffer.slice(..);
+        let (tx, rx) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |res| {
+            tx.send(res).unwrap();
+        });
+        device.poll(wgpu::Maintain::Wait);
+        rx.recv().unwrap().expect("Mapping frame buffer failed");
+
+        let row_size = (self.width * 4) as usize;
+        let mut pixels = Vec::with_capacity(row_size * self.height as usize);
+        {
+            let data = slice.get_mapped_range();
+            for row in data.chunks(self.padded_bytes_per_row as usize) {
+                pixels.extend_from_slice(&row[.. row_size]);
+            }
+        }
+        self.buffer.unmap();
+        if self.bgra {
+            for pixel in pixels.chunks_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        pixels
+    }
+}
@@ -1,6 +1,16 @@
 use bytemuck::{Pod, Zeroable};
-use wgpu;
 
+use crate::config::MAX_SPECIES;
+
+
+// Limits to request the device with, the agent pass binds one storage
+// buffer more than the defaults allow
+pub fn device_limits() -> wgpu::Limits {
+    wgpu::Limits {
+        max_storage_buffers_per_shader_stage: 9,
+        ..wgpu::Limits::default()
+    }
+}
 
 // The vertex type that we will use to represent a point on our triangle.
 #[repr(C)]
@@ -10,33 +20,92 @@ pub struct Vertex {
 }
 
 #[repr(C)]
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug)]
 pub struct Uniforms {  // parameter
     pub n_agents: u32,
     pub size_x: u32,
     pub size_y: u32,
-    pub deposit: f32,
+    pub n_species: u32,
+    // trail channels of size_x * size_y, one per species and
+    // one repellent trail per species if any species deposits it
+    pub n_channels: u32,
     pub decay: f32,
-    pub v: f32,
-    pub phi_sens: f32,  // Sensor angle
-    pub turn_speed: f32,  // turn speed in rad per step
+    pub repellent_decay: f32,
     pub sens_range_min: f32,
     pub sens_range_max: f32,
     pub sense_steps: f32,
-    pub w_nutriment: f32,
     pub seed: u32,
+    pub deposit_mode: u32,  // 0: float, 1: atomic fixed point
+    pub boundary: u32,  // 0: clamp, 1: wrap, 2: reflect, 3: respawn, 4: absorb
+    pub color_sampling: u32,  // 0: on nutriment, 1: every step
+    pub color_blend: f32,
+    pub consumption: f32,  // nutriment eaten by each agent passing a cell
+    pub regrowth: f32,  // share of the eaten nutriment growing back
+    pub nutriment_diffusion: f32,
+    pub colormap: u32,  // 0: species colours, 1: lookup table
+    pub tone_mapping: u32,  // 0: clamp, 1: log, 2: reinhard, 3: aces
+    pub exposure: f32,
+    pub gamma: f32,
+    pub white: f32,  // trail mapped to 1 by log and reinhard
+    // layers of the grid, opacity 0 if hidden,
+    // blend 0: add, 1: screen, 2: multiply
+    pub nutriment_opacity: f32,
+    pub nutriment_blend: u32,
+    pub trails_opacity: f32,
+    pub trails_blend: u32,
+    pub obstacles_opacity: f32,
+    pub obstacles_blend: u32,
+    // fixed point scale of atomic deposits, see deposit_scale
+    pub deposit_scale: f32
+}
+
+// x coordinate of agents removed by the absorbing boundary
+pub const ABSORBED: f32 = -1e30;
+
+// Finest fixed point scale of atomic deposits
+pub const MAX_DEPOSIT_SCALE: f32 = 1048576.;
+
+// Fixed point scale of atomic deposits, the largest power of two at which
+// n_agents rounded deposits of max_amount still fit into one u32 cell.
+// None if not even a scale of 1 fits.
+pub fn deposit_scale(n_agents: u32, max_amount: f32) -> Option<f32> {
+    let limit = (u32::MAX as f64 / n_agents.max(1) as f64 - 0.5) /
+                max_amount as f64;
+    if limit < 1. {
+        return None;
+    }
+    Some((limit.log2().floor().exp2() as f32).min(MAX_DEPOSIT_SCALE))
 }
 
 #[repr(C)]
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug)]
 pub struct Agent {
     pub x: f32,
     pub y: f32,
-    pub phi: f32
+    pub phi: f32,
+    pub species: u32,
+    pub color: Color
 }
 
+// Movement parameters and trail colour of one species
 #[repr(C)]
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug)]
+pub struct SpeciesParams {
+    pub v: f32,
+    pub deposit: f32,
+    pub repellent: f32,  // deposit into the repellent trail
+    pub phi_sens: f32,  // Sensor angle
+    pub turn_speed: f32,  // turn speed in rad per step
+    pub w_nutriment: f32,
+    pub color: Color,
+    // sensor weight of the trail of each species
+    pub interaction: [f32; MAX_SPECIES],
+    // sensor weight of the repellent trail of each species
+    pub repulsion: [f32; MAX_SPECIES]
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
 pub struct Color {
     pub r: f32,
     pub g: f32,
@@ -51,6 +120,8 @@ unsafe impl Zeroable for Vertex {}
 unsafe impl Pod for Vertex {}
 unsafe impl Zeroable for Color {}
 unsafe impl Pod for Color {}
+unsafe impl Zeroable for SpeciesParams {}
+unsafe impl Pod for SpeciesParams {}
 
 pub fn create_bind_group_layout_compute_agents(device: &wgpu::Device)
 -> wgpu::BindGroupLayout
@@ -112,7 +183,7 @@ pub fn create_bind_group_layout_compute_agents(device: &wgpu::Device)
                     visibility: wgpu::ShaderStages::COMPUTE,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Storage {
-                            read_only: false },
+                            read_only: true },
                         has_dynamic_offset: false,
                         min_binding_size: None },
                     count: None
@@ -136,6 +207,26 @@ pub fn create_bind_group_layout_compute_agents(device: &wgpu::Device)
                         has_dynamic_offset: false,
                         min_binding_size: None },
                     count: None
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage {
+                            read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None },
+                    count: None
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 9,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage {
+                            read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None },
+                    count: None
                 }
             ],
             label: None,
@@ -177,6 +268,107 @@ pub fn create_bind_group_layout_compute_slime(device: &wgpu::Device)
                         has_dynamic_offset: false,
                         min_binding_size: None },
                     count: None
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage {
+                            read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None },
+                    count: None
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage {
+                            read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None },
+                    count: None
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage {
+                            read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None },
+                    count: None
+                }
+            ],
+            label: None,
+        }
+    )
+}
+
+pub fn create_bind_group_layout_compute_nutriment(device: &wgpu::Device)
+-> wgpu::BindGroupLayout
+{
+    device.create_bind_group_layout(
+        &wgpu::BindGroupLayoutDescriptor{
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage {
+                            read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None },
+                    count: None
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage {
+                            read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None },
+                    count: None
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None },
+                    count: None
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage {
+                            read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None },
+                    count: None
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage {
+                            read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None },
+                    count: None
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage {
+                            read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None },
+                    count: None
                 }
             ],
             label: None,
@@ -219,6 +411,56 @@ pub fn create_bind_group_layout_render(
                         has_dynamic_offset: false,
                         min_binding_size: None },
                     count: None
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage {
+                            read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None },
+                    count: None
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage {
+                            read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None },
+                    count: None
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage {
+                            read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None },
+                    count: None
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage {
+                            read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None },
+                    count: None
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage {
+                            read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None },
+                    count: None
                 }
             ],
             label: Some("Render Layout"),
@@ -226,17 +468,20 @@ pub fn create_bind_group_layout_render(
     )
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn create_physarum_bind_group(
     device: &wgpu::Device,
     layout: &wgpu::BindGroupLayout,
     agents: &wgpu::Buffer,
     slime_in: &wgpu::Buffer,
-    slime_out: &wgpu::Buffer,
+    deposit: &wgpu::Buffer,
     nutriment: &wgpu::Buffer,
     uniform_buffer: &wgpu::Buffer,
-    agent_color_buffer: &wgpu::Buffer,
+    obstacles: &wgpu::Buffer,
     slime_color_buffer: &wgpu::Buffer,
-    nutriment_color_buffer: &wgpu::Buffer)
+    nutriment_color_buffer: &wgpu::Buffer,
+    species: &wgpu::Buffer,
+    visits: &wgpu::Buffer)
 -> wgpu::BindGroup
 {
     device.create_bind_group(&wgpu::BindGroupDescriptor {
@@ -253,7 +498,7 @@ pub fn create_physarum_bind_group(
             },
             wgpu::BindGroupEntry {
                 binding: 2,
-                resource: slime_out.as_entire_binding()
+                resource: deposit.as_entire_binding()
             },
             wgpu::BindGroupEntry {
                 binding: 3,
@@ -265,7 +510,7 @@ pub fn create_physarum_bind_group(
             },
             wgpu::BindGroupEntry {
                 binding: 5,
-                resource: agent_color_buffer.as_entire_binding()
+                resource: obstacles.as_entire_binding()
             },
             wgpu::BindGroupEntry {
                 binding: 6,
@@ -274,17 +519,29 @@ pub fn create_physarum_bind_group(
             wgpu::BindGroupEntry {
                 binding: 7,
                 resource: nutriment_color_buffer.as_entire_binding()
+            },
+            wgpu::BindGroupEntry {
+                binding: 8,
+                resource: species.as_entire_binding()
+            },
+            wgpu::BindGroupEntry {
+                binding: 9,
+                resource: visits.as_entire_binding()
             }
         ]
     })
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn create_slime_bind_group(
     device: &wgpu::Device,
     layout: &wgpu::BindGroupLayout,
     slime_in: &wgpu::Buffer,
-    slime_out: &wgpu::Buffer,  // intermediate result for dissipation
-    uniform_buffer: &wgpu::Buffer)
+    slime_out: &wgpu::Buffer,
+    uniform_buffer: &wgpu::Buffer,
+    deposit: &wgpu::Buffer,  // agent deposits of this step
+    deposit_next: &wgpu::Buffer,  // cleared for the next step
+    obstacles: &wgpu::Buffer)
 -> wgpu::BindGroup
 {
     device.create_bind_group(&wgpu::BindGroupDescriptor {
@@ -302,17 +559,79 @@ pub fn create_slime_bind_group(
             wgpu::BindGroupEntry {
                 binding: 2,
                 resource: uniform_buffer.as_entire_binding()
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: deposit.as_entire_binding()
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: deposit_next.as_entire_binding()
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: obstacles.as_entire_binding()
+            }
+        ]
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn create_nutriment_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    nutriment_in: &wgpu::Buffer,
+    nutriment_out: &wgpu::Buffer,
+    uniform_buffer: &wgpu::Buffer,
+    visits: &wgpu::Buffer,  // agents passing each cell in this step
+    capacity: &wgpu::Buffer,  // initial nutriment
+    obstacles: &wgpu::Buffer)
+-> wgpu::BindGroup
+{
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        label: Some("Nutriment BG"),
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: nutriment_in.as_entire_binding()
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: nutriment_out.as_entire_binding()
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: uniform_buffer.as_entire_binding()
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: visits.as_entire_binding()
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: capacity.as_entire_binding()
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: obstacles.as_entire_binding()
             }
         ]
     })
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn create_render_bind_group(
     device: &wgpu::Device,
     layout: &wgpu::BindGroupLayout,
     slime: &wgpu::Buffer,
     uniform_buffer: &wgpu::Buffer,
-    slime_color_buffer: &wgpu::Buffer)
+    slime_color_buffer: &wgpu::Buffer,
+    species: &wgpu::Buffer,
+    colormap: &wgpu::Buffer,
+    nutriment: &wgpu::Buffer,
+    nutriment_color_buffer: &wgpu::Buffer,
+    obstacles: &wgpu::Buffer)
 -> wgpu::BindGroup
 {
     device.create_bind_group(&wgpu::BindGroupDescriptor {
@@ -330,6 +649,26 @@ pub fn create_render_bind_group(
             wgpu::BindGroupEntry {
                 binding: 2,
                 resource: slime_color_buffer.as_entire_binding()
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: species.as_entire_binding()
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: colormap.as_entire_binding()
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: nutriment.as_entire_binding()
+            },
+            wgpu::BindGroupEntry {
+                binding: 6,
+                resource: nutriment_color_buffer.as_entire_binding()
+            },
+            wgpu::BindGroupEntry {
+                binding: 7,
+                resource: obstacles.as_entire_binding()
             }
         ]
     })
@@ -343,7 +682,7 @@ pub fn create_pipeline_layout(
 {
     let desc = wgpu::PipelineLayoutDescriptor {
         label: Some(label),
-        bind_group_layouts: &[&bind_group_layout],
+        bind_group_layouts: &[bind_group_layout],
         push_constant_ranges: &[],
     };
     device.create_pipeline_layout(&desc)
@@ -359,12 +698,34 @@ pub fn create_compute_pipeline(
     let desc = wgpu::ComputePipelineDescriptor {
         label: Some(label),
         layout: Some(layout),
-        module: &cs_mod,
+        module: cs_mod,
         entry_point: "main",
     };
     device.create_compute_pipeline(&desc)
 }
 
+impl Agent {
+    // Per instance position, heading and colour for the sprites
+    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Agent>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: 16,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x3,
+                }
+            ]
+        }
+    }
+}
+
 impl Vertex {
     pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
         wgpu::VertexBufferLayout {
@@ -0,0 +1,577 @@
+// Parameter panel drawn over the simulation, egui fed with winit events
+use std::{f32::consts::PI, time::Instant};
+use winit::{event::*, window::Window};
+
+use physarum_art::colormap::Colormap;
+use physarum_art::config::{AgentColoring, BlendMode, Boundary, ColorSampling,
+                           Config, DepositMode, Layer, Species, ToneMapping,
+                           MAX_BLOOM_RADIUS, MAX_SPECIES, MAX_SPRITE_SIZE};
+use physarum_art::simulation::{max_agents, max_grid_size};
+
+use crate::gui_painter::Painter;
+
+
+// Requests of the panel buttons and key bindings
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    ResetAgents,  // new simulation with the edited configuration
+    Randomise,  // new random parameters and simulation
+    Save,  // write the edited configuration to ./runs
+    Pause,  // stop or resume stepping
+    Step,  // one step while paused
+    Screenshot,  // write the grid as PNG to ./screenshots
+    Fullscreen,  // toggle borderless fullscreen
+    TogglePanel
+}
+
+// Key binding of each action
+pub const KEYS: [(VirtualKeyCode, Action); 8] = [
+    (VirtualKeyCode::Space, Action::Pause),
+    (VirtualKeyCode::S, Action::Step),
+    (VirtualKeyCode::R, Action::ResetAgents),
+    (VirtualKeyCode::N, Action::Randomise),
+    (VirtualKeyCode::C, Action::Save),
+    (VirtualKeyCode::P, Action::Screenshot),
+    (VirtualKeyCode::F11, Action::Fullscreen),
+    (VirtualKeyCode::F1, Action::TogglePanel)
+];
+
+pub struct Gui {
+    ctx: egui::Context,
+    input: egui::RawInput,
+    pointer: egui::Pos2,
+    start: Instant,
+    painter: Painter,
+    primitives: Vec<egui::ClippedPrimitive>,
+    textures_delta: Option<egui::TexturesDelta>,
+    limits: wgpu::Limits,  // bounds of the run setup
+    pub visible: bool,
+    pub error: Option<String>  // shown in the panel until the next change
+}
+
+impl Gui {
+    // format is the format of the texture painted on
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        Self {
+            ctx: egui::Context::default(),
+            input: egui::RawInput::default(),
+            pointer: egui::Pos2::ZERO,
+            start: Instant::now(),
+            painter: Painter::new(device, format),
+            primitives: Vec::new(),
+            textures_delta: None,
+            limits: device.limits(),
+            visible: true,
+            error: None
+        }
+    }
+
+    // Queue the event for the next frame,
+    // true if the panel uses it and the window should ignore it
+    pub fn input(&mut self, window: &Window, event: &WindowEvent) -> bool {
+        if !self.visible {
+            return false;
+        }
+        let pixels_per_point = window.scale_factor() as f32;
+        let modifiers = self.input.modifiers;
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.pointer = egui::pos2(position.x as f32 / pixels_per_point,
+                                          position.y as f32 / pixels_per_point);
+                self.input.events.push(egui::Event::PointerMoved(self.pointer));
+                self.ctx.is_pointer_over_area()
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.input.events.push(egui::Event::PointerGone);
+                false
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let button = match button {
+                    MouseButton::Left => egui::PointerButton::Primary,
+                    MouseButton::Right => egui::PointerButton::Secondary,
+                    MouseButton::Middle => egui::PointerButton::Middle,
+                    MouseButton::Other(_) => return false
+                };
+                self.input.events.push(egui::Event::PointerButton {
+                    pos: self.pointer,
+                    button,
+                    pressed: *state == ElementState::Pressed,
+                    modifiers
+                });
+                self.ctx.wants_pointer_input()
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let delta = match delta {
+                    MouseScrollDelta::LineDelta(x, y) =>
+                        egui::vec2(*x, *y) * 50.,
+                    MouseScrollDelta::PixelDelta(p) =>
+                        egui::vec2(p.x as f32, p.y as f32) / pixels_per_point
+                };
+                self.input.events.push(egui::Event::Scroll(delta));
+                self.ctx.is_pointer_over_area()
+            }
+            WindowEvent::ModifiersChanged(state) => {
+                self.input.modifiers = egui::Modifiers {
+                    alt: state.alt(),
+                    ctrl: state.ctrl(),
+                    shift: state.shift(),
+                    mac_cmd: cfg!(target_os = "macos") && state.logo(),
+                    command: if cfg!(target_os = "macos") {
+                        state.logo()
+                    } else {
+                        state.ctrl()
+                    }
+                };
+                false
+            }
+            WindowEvent::ReceivedCharacter(c) if !c.is_control() => {
+                self.input.events.push(egui::Event::Text(c.to_string()));
+                self.ctx.wants_keyboard_input()
+            }
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    virtual_keycode: Some(keycode), state, ..
+                },
+                ..
+            } => {
+                if let Some(key) = egui_key(*keycode) {
+                    self.input.events.push(egui::Event::Key {
+                        key,
+                        pressed: *state == ElementState::Pressed,
+                        modifiers
+                    });
+                }
+                self.ctx.wants_keyboard_input()
+            }
+            _ => false
+        }
+    }
+
+    // Lay out the panel for cfg, edits are written to cfg directly
+    pub fn update(&mut self, window: &Window, cfg: &mut Config)
+    -> Vec<Action> {
+        let size = window.inner_size();
+        let pixels_per_point = window.scale_factor() as f32;
+        let mut input = std::mem::take(&mut self.input);
+        self.input.modifiers = input.modifiers;
+        input.screen_rect = Some(egui::Rect::from_min_size(
+            egui::Pos2::ZERO,
+            egui::vec2(size.width as f32, size.height as f32) /
+                pixels_per_point));
+        input.pixels_per_point = Some(pixels_per_point);
+        input.time = Some(self.start.elapsed().as_secs_f64());
+
+        let mut actions = Vec::new();
+        let visible = self.visible;
+        let error = self.error.clone();
+        let limits = &self.limits;
+        let output = self.ctx.run(input, |ctx| {
+            if visible {
+                panel(ctx, cfg, &error, limits, &mut actions);
+            }
+        });
+        self.primitives = self.ctx.tessellate(output.shapes);
+        self.textures_delta = Some(output.textures_delta);
+        actions
+    }
+
+    // Record drawing the panel over view
+    pub fn paint(&mut self, window: &Window, device: &wgpu::Device,
+                 queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder,
+                 view: &wgpu::TextureView, size: [u32; 2]) {
+        if let Some(delta) = self.textures_delta.take() {
+            self.painter.update_textures(device, queue, &delta);
+        }
+        self.painter.paint(device, queue, encoder, view, size,
+                           window.scale_factor() as f32, &self.primitives);
+    }
+}
+
+// Keys used by text fields and keyboard navigation of the panel
+fn egui_key(key: VirtualKeyCode) -> Option<egui::Key> {
+    Some(match key {
+        VirtualKeyCode::Down => egui::Key::ArrowDown,
+        VirtualKeyCode::Left => egui::Key::ArrowLeft,
+        VirtualKeyCode::Right => egui::Key::ArrowRight,
+        VirtualKeyCode::Up => egui::Key::ArrowUp,
+        VirtualKeyCode::Escape => egui::Key::Escape,
+        VirtualKeyCode::Tab => egui::Key::Tab,
+        VirtualKeyCode::Back => egui::Key::Backspace,
+        VirtualKeyCode::Return => egui::Key::Enter,
+        VirtualKeyCode::Space => egui::Key::Space,
+        VirtualKeyCode::Delete => egui::Key::Delete,
+        VirtualKeyCode::Home => egui::Key::Home,
+        VirtualKeyCode::End => egui::Key::End,
+        VirtualKeyCode::A => egui::Key::A,
+        VirtualKeyCode::Z => egui::Key::Z,
+        _ => return None
+    })
+}
+
+// Slider for a value of a species that falls back to the top level value
+fn species_value(ui: &mut egui::Ui, label: &str, value: &mut Option<f32>,
+                 top_level: f32, range: std::ops::RangeInclusive<f32>) {
+    ui.horizontal(|ui| {
+        let mut own = value.is_some();
+        if ui.checkbox(&mut own, "").on_hover_text("own value").changed() {
+            *value = own.then_some(top_level);
+        }
+        match value {
+            Some(v) => ui.add(egui::Slider::new(v, range).text(label)),
+            None => ui.label(format!("{}: {:.4}", label, top_level))
+        };
+    });
+}
+
+// Trail weights of a species, stored once one of them is changed
+fn weights(ui: &mut egui::Ui, label: &str, stored: &mut Vec<f32>, n: usize,
+           default: impl Fn(usize) -> f32) {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        let mut values: Vec<f32> = (0 .. n).map(&default).collect();
+        let mut changed = false;
+        for w in values.iter_mut() {
+            changed |= ui.add(egui::DragValue::new(w).speed(0.01)
+                                 .clamp_range(-10. ..= 10.))
+                         .changed();
+        }
+        if changed {
+            *stored = values;
+        }
+    });
+}
+
+// Colour map choice, gradient stops and image path
+fn colormap(ui: &mut egui::Ui, colormap: &mut Colormap) {
+    let name = |c: &Colormap| match c {
+        Colormap::Species => "species",
+        Colormap::Magma => "magma",
+        Colormap::Viridis => "viridis",
+        Colormap::Inferno => "inferno",
+        Colormap::Gradient(_) => "gradient",
+        Colormap::Image(_) => "image"
+    };
+    // a gradient or image keeps its stops or path while selected
+    let gradient = match colormap {
+        Colormap::Gradient(_) => colormap.clone(),
+        _ => Colormap::Gradient(vec![[0., 0., 0.], [1., 1., 1.]])
+    };
+    let image = match colormap {
+        Colormap::Image(_) => colormap.clone(),
+        _ => Colormap::Image(String::new())
+    };
+    egui::ComboBox::from_label("colormap")
+        .selected_text(name(colormap))
+        .show_ui(ui, |ui| {
+            for choice in [Colormap::Species, Colormap::Magma,
+                           Colormap::Viridis, Colormap::Inferno,
+                           gradient, image] {
+                let text = name(&choice);
+                ui.selectable_value(colormap, choice, text);
+            }
+        });
+
+    match colormap {
+        Colormap::Gradient(colors) => {
+            ui.horizontal(|ui| {
+                for color in colors.iter_mut() {
+                    ui.color_edit_button_rgb(color);
+                }
+                if ui.button("+").clicked() {
+                    let last = *colors.last().unwrap_or(&[1., 1., 1.]);
+                    colors.push(last);
+                }
+                if colors.len() > 2 && ui.button("-").clicked() {
+                    colors.pop();
+                }
+            });
+        }
+        Colormap::Image(path) => {
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(path);
+                ui.label("lookup image");
+            });
+        }
+        _ => {}
+    }
+}
+
+// Toggle, opacity and blend mode of a render layer in one row
+fn layer(ui: &mut egui::Ui, name: &str, layer: &mut Layer) {
+    ui.horizontal(|ui| {
+        ui.checkbox(&mut layer.visible, name);
+        ui.add(egui::Slider::new(&mut layer.opacity, 0. ..= 1.));
+        egui::ComboBox::from_id_source(name)
+            .selected_text(format!("{:?}", layer.blend))
+            .show_ui(ui, |ui| {
+                for blend in [BlendMode::Add, BlendMode::Screen,
+                              BlendMode::Multiply] {
+                    ui.selectable_value(&mut layer.blend, blend,
+                                        format!("{:?}", blend));
+                }
+            });
+    });
+}
+
+// Seed as text, a drag value works in f64 and would round large seeds.
+// Unparsable text is kept while edited, any other text follows the seed.
+fn seed_edit(ui: &mut egui::Ui, seed: &mut u64) {
+    let id = ui.make_persistent_id("seed");
+    let stored = ui.data().get_temp::<String>(id);
+    let mut text = match stored {
+        Some(text) if text.parse::<u64>().map_or(true, |s| s == *seed) => text,
+        _ => seed.to_string()
+    };
+    if ui.text_edit_singleline(&mut text).changed() {
+        if let Ok(value) = text.parse() {
+            *seed = value;
+        }
+    }
+    ui.data().insert_temp(id, text);
+}
+
+fn panel(ctx: &egui::Context, cfg: &mut Config, error: &Option<String>,
+         limits: &wgpu::Limits, actions: &mut Vec<Action>) {
+    egui::Window::new("Physarum").vscroll(true).show(ctx, |ui| {
+        ui.horizontal(|ui| {
+            if ui.button("Reset agents").clicked() {
+                actions.push(Action::ResetAgents);
+            }
+            if ui.button("Randomise").clicked() {
+                actions.push(Action::Randomise);
+            }
+            if ui.button("Save config").clicked() {
+                actions.push(Action::Save);
+            }
+        });
+        ui.collapsing("Keys", |ui| {
+            for (key, action) in KEYS {
+                ui.label(format!("{:?}: {:?}", key, action));
+            }
+        });
+        if let Some(error) = error {
+            ui.colored_label(egui::Color32::LIGHT_RED, error);
+        }
+
+        ui.collapsing("Agents", |ui| {
+            ui.add(egui::Slider::new(&mut cfg.v, 0. ..= 25.).text("v"));
+            ui.add(egui::Slider::new(&mut cfg.phi_sens, 0. ..= PI)
+                .text("phi_sens"));
+            ui.add(egui::Slider::new(&mut cfg.turn_speed, 0. ..= PI)
+                .text("turn_speed"));
+            ui.add(egui::Slider::new(&mut cfg.sens_range_min, 0. ..= 50.)
+                .text("sens_range_min"));
+            ui.add(egui::Slider::new(&mut cfg.sens_range_max, 0. ..= 100.)
+                .text("sens_range_max"));
+            ui.add(egui::Slider::new(&mut cfg.w_nutriment, -10. ..= 10.)
+                .text("w_nutriment"));
+        });
+
+        ui.collapsing("Trails", |ui| {
+            ui.add(egui::Slider::new(&mut cfg.deposit, 0. ..= 0.2)
+                .logarithmic(true).text("deposit"));
+            ui.add(egui::Slider::new(&mut cfg.decay, 0. ..= 1.)
+                .text("decay"));
+            ui.add(egui::Slider::new(&mut cfg.repellent, 0. ..= 0.2)
+                .logarithmic(true).text("repellent"))
+                .on_hover_text("only if started with a repellent");
+            ui.add(egui::Slider::new(&mut cfg.repellent_decay, 0. ..= 1.)
+                .text("repellent_decay"));
+            egui::ComboBox::from_label("deposit_mode")
+                .selected_text(format!("{:?}", cfg.deposit_mode))
+                .show_ui(ui, |ui| {
+                    for mode in [DepositMode::Float, DepositMode::Atomic] {
+                        ui.selectable_value(&mut cfg.deposit_mode, mode,
+                                            format!("{:?}", mode));
+                    }
+                });
+            egui::ComboBox::from_label("boundary")
+                .selected_text(format!("{:?}", cfg.boundary))
+                .show_ui(ui, |ui| {
+                    for boundary in [Boundary::Clamp, Boundary::Wrap,
+                                     Boundary::Reflect, Boundary::Respawn,
+                                     Boundary::Absorb] {
+                        ui.selectable_value(&mut cfg.boundary, boundary,
+                                            format!("{:?}", boundary));
+                    }
+                });
+        });
+
+        ui.collapsing("Colours", |ui| {
+            colormap(ui, &mut cfg.colormap);
+            egui::ComboBox::from_label("tone_mapping")
+                .selected_text(format!("{:?}", cfg.tone_mapping))
+                .show_ui(ui, |ui| {
+                    for tone_mapping in [ToneMapping::Clamp, ToneMapping::Log,
+                                         ToneMapping::Reinhard,
+                                         ToneMapping::Aces] {
+                        ui.selectable_value(&mut cfg.tone_mapping, tone_mapping,
+                                            format!("{:?}", tone_mapping));
+                    }
+                });
+            ui.add(egui::Slider::new(&mut cfg.exposure, 0.01 ..= 100.)
+                .logarithmic(true).text("exposure"));
+            ui.add(egui::Slider::new(&mut cfg.gamma, 0.2 ..= 5.)
+                .text("gamma"));
+            ui.add(egui::Slider::new(&mut cfg.white, 0.1 ..= 100.)
+                .logarithmic(true).text("white"))
+                .on_hover_text("log and reinhard only");
+        });
+
+        ui.collapsing("Layers", |ui| {
+            let layers = &mut cfg.layers;
+            layer(ui, "nutriment", &mut layers.nutriment);
+            layer(ui, "trails", &mut layers.trails);
+            layer(ui, "obstacles", &mut layers.obstacles);
+            layer(ui, "agents", &mut layers.agents);
+            egui::ComboBox::from_label("agent_coloring")
+                .selected_text(format!("{:?}", layers.agent_coloring))
+                .show_ui(ui, |ui| {
+                    for coloring in [AgentColoring::Color,
+                                     AgentColoring::Heading] {
+                        ui.selectable_value(&mut layers.agent_coloring,
+                                            coloring,
+                                            format!("{:?}", coloring));
+                    }
+                });
+            ui.add(egui::Slider::new(&mut layers.agent_size,
+                                     1. ..= MAX_SPRITE_SIZE)
+                .text("agent_size"));
+        });
+
+        ui.collapsing("Post processing", |ui| {
+            ui.add(egui::Slider::new(&mut cfg.bloom, 0. ..= 4.)
+                .text("bloom"));
+            ui.add(egui::Slider::new(&mut cfg.bloom_threshold, 0. ..= 1.)
+                .text("bloom_threshold"));
+            ui.add(egui::Slider::new(&mut cfg.bloom_radius,
+                                     0.5 ..= MAX_BLOOM_RADIUS)
+                .logarithmic(true).text("bloom_radius"));
+            ui.add(egui::Slider::new(&mut cfg.vignette, 0. ..= 1.)
+                .text("vignette"))
+                .on_hover_text("off with the wrap boundary");
+            ui.add(egui::Slider::new(&mut cfg.grain, 0. ..= 1.)
+                .text("grain"));
+        });
+
+        ui.collapsing("Nutriment", |ui| {
+            ui.add(egui::Slider::new(&mut cfg.consumption, 0. ..= 1.)
+                .logarithmic(true).text("consumption"));
+            ui.add(egui::Slider::new(&mut cfg.regrowth, 0. ..= 1.)
+                .logarithmic(true).text("regrowth"));
+            ui.add(egui::Slider::new(&mut cfg.nutriment_diffusion, 0. ..= 1.)
+                .text("nutriment_diffusion"));
+            egui::ComboBox::from_label("color_sampling")
+                .selected_text(format!("{:?}", cfg.color_sampling))
+                .show_ui(ui, |ui| {
+                    for sampling in [ColorSampling::Nutriment,
+                                     ColorSampling::Continuous] {
+                        ui.selectable_value(&mut cfg.color_sampling, sampling,
+                                            format!("{:?}", sampling));
+                    }
+                });
+            ui.add(egui::Slider::new(&mut cfg.color_blend, 0. ..= 1.)
+                .logarithmic(true).text("color_blend"));
+        });
+
+        let n = cfg.species.len();
+        let top = cfg.clone();
+        ui.collapsing(format!("Species ({})", n.max(1)), |ui| {
+            for (s, sp) in cfg.species.iter_mut().enumerate() {
+                ui.push_id(s, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("Species {}", s));
+                        ui.color_edit_button_rgb(&mut sp.color);
+                    });
+                    species_value(ui, "v", &mut sp.v, top.v, 0. ..= 25.);
+                    species_value(ui, "phi_sens", &mut sp.phi_sens,
+                                  top.phi_sens, 0. ..= PI);
+                    species_value(ui, "turn_speed", &mut sp.turn_speed,
+                                  top.turn_speed, 0. ..= PI);
+                    species_value(ui, "deposit", &mut sp.deposit,
+                                  top.deposit, 0. ..= 0.2);
+                    species_value(ui, "repellent", &mut sp.repellent,
+                                  top.repellent, 0. ..= 0.2);
+                    species_value(ui, "w_nutriment", &mut sp.w_nutriment,
+                                  top.w_nutriment, -10. ..= 10.);
+                    let interaction: Vec<f32> = (0 .. n)
+                        .map(|t| sp.interaction_with(s, t)).collect();
+                    weights(ui, "interaction", &mut sp.interaction, n,
+                            |t| interaction[t]);
+                    let repulsion: Vec<f32> = (0 .. n)
+                        .map(|t| sp.repulsion_from(t)).collect();
+                    weights(ui, "repulsion", &mut sp.repulsion, n,
+                            |t| repulsion[t]);
+                    ui.separator();
+                });
+            }
+            ui.horizontal(|ui| {
+                if n < MAX_SPECIES && ui.button("Add species").clicked() {
+                    // stored weights need one for the new species
+                    for sp in cfg.species.iter_mut() {
+                        for weights in [&mut sp.interaction,
+                                        &mut sp.repulsion] {
+                            if !weights.is_empty() {
+                                weights.push(-1.);
+                            }
+                        }
+                    }
+                    cfg.species.push(Species::default());
+                }
+                if n > 0 && ui.button("Remove species").clicked() {
+                    cfg.species.pop();
+                    for sp in cfg.species.iter_mut() {
+                        sp.interaction.truncate(n - 1);
+                        sp.repulsion.truncate(n - 1);
+                    }
+                }
+            });
+        });
+
+        ui.collapsing("Start (applied on reset)", |ui| {
+            ui.add(egui::Slider::new(&mut cfg.r_init, 1. ..= 500.)
+                .text("r_init"));
+            ui.add(egui::Slider::new(&mut cfg.n_fix, 0 ..= 50).text("n_fix"));
+            ui.add(egui::Slider::new(&mut cfg.r_fix_min, 1 ..= 50)
+                .text("r_fix_min"));
+            ui.add(egui::Slider::new(&mut cfg.r_fix_max, 2 ..= 100)
+                .text("r_fix_max"));
+            let images = [("nutriment_image", &mut cfg.nutriment_image),
+                          ("obstacle_image", &mut cfg.obstacle_image)];
+            for (label, image) in images {
+                ui.horizontal(|ui| {
+                    let mut path = image.clone().unwrap_or_default();
+                    if ui.text_edit_singleline(&mut path).changed() {
+                        *image = (!path.is_empty()).then_some(path);
+                    }
+                    ui.label(label);
+                });
+            }
+            // bounds of the device, grids too large in total are
+            // reported by the reset
+            let max_size = max_grid_size(limits);
+            let run_setup = [("size_x", &mut cfg.size_x, 1, max_size),
+                             ("size_y", &mut cfg.size_y, 1, max_size),
+                             ("n_agents", &mut cfg.n_agents, 1,
+                              max_agents(limits)),
+                             ("shader_seed", &mut cfg.shader_seed, 0,
+                              u32::MAX)];
+            for (label, value, min, max) in run_setup {
+                if let Some(value) = value {
+                    ui.horizontal(|ui| {
+                        ui.add(egui::DragValue::new(value)
+                                   .clamp_range(min ..= max));
+                        ui.label(label);
+                    });
+                }
+            }
+            if let Some(seed) = &mut cfg.seed {
+                ui.horizontal(|ui| {
+                    seed_edit(ui, seed);
+                    ui.label("seed");
+                });
+            }
+            ui.label(format!("timeline: {} keyframes", cfg.timeline.len()));
+        });
+    });
+}
@@ -0,0 +1,353 @@
+// Draws the triangles tessellated by egui with an own wgpu pipeline
+use bytemuck::{Pod, Zeroable};
+use std::{collections::HashMap, num::NonZeroU32};
+
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct Screen {
+    size: [f32; 2],  // in points
+    srgb: u32,
+    _padding: u32
+}
+
+unsafe impl Zeroable for Screen {}
+unsafe impl Pod for Screen {}
+
+pub struct Painter {
+    pipeline: wgpu::RenderPipeline,
+    screen_buffer: wgpu::Buffer,
+    screen_bind_group: wgpu::BindGroup,
+    texture_layout: wgpu::BindGroupLayout,
+    srgb: bool,
+    textures: HashMap<egui::TextureId, (wgpu::Texture, wgpu::BindGroup)>,
+    // meshes of a frame one after the other and the buffer sizes, only
+    // grown
+    vertices: (wgpu::Buffer, u64),
+    indices: (wgpu::Buffer, u64)
+}
+
+// Buffer for the meshes, size in bytes
+fn mesh_buffer(device: &wgpu::Device, label: &str, usage: wgpu::BufferUsages,
+               size: u64) -> (wgpu::Buffer, u64) {
+    (device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size,
+        usage: usage | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false
+    }), size)
+}
+
+impl Painter {
+    // format is the format of the texture painted on
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(
+            wgpu::include_wgsl!("../Shader/egui.wgsl"));
+
+        let screen_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Panel Screen"),
+            size: std::mem::size_of::<Screen>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false
+        });
+        let screen_layout = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None },
+                        count: None
+                    }
+                ],
+                label: Some("Panel Screen Layout")
+            });
+        let screen_bind_group = device.create_bind_group(
+            &wgpu::BindGroupDescriptor {
+                layout: &screen_layout,
+                label: Some("Panel Screen BG"),
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: screen_buffer.as_entire_binding()
+                    }
+                ]
+            });
+        let texture_layout = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float {
+                                filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false },
+                        count: None
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(
+                            wgpu::SamplerBindingType::Filtering),
+                        count: None
+                    }
+                ],
+                label: Some("Panel Texture Layout")
+            });
+
+        let layout = device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Panel"),
+                bind_group_layouts: &[&screen_layout, &texture_layout],
+                push_constant_ranges: &[]
+            });
+        let pipeline = device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("Panel Pipeline"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    // egui::epaint::Vertex: pos, uv, sRGBA
+                    buffers: &[wgpu::VertexBufferLayout {
+                        array_stride: 5 * 4,
+                        step_mode: wgpu::VertexStepMode::Vertex,
+                        attributes: &wgpu::vertex_attr_array![
+                            0 => Float32x2, 1 => Float32x2, 2 => Unorm8x4]
+                    }]
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        // egui colours are premultiplied
+                        blend: Some(wgpu::BlendState {
+                            color: wgpu::BlendComponent {
+                                src_factor: wgpu::BlendFactor::One,
+                                dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                                operation: wgpu::BlendOperation::Add
+                            },
+                            alpha: wgpu::BlendComponent {
+                                src_factor: wgpu::BlendFactor::OneMinusDstAlpha,
+                                dst_factor: wgpu::BlendFactor::One,
+                                operation: wgpu::BlendOperation::Add
+                            }
+                        }),
+                        write_mask: wgpu::ColorWrites::ALL
+                    })]
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    cull_mode: None,
+                    ..Default::default()
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None
+            });
+
+        Self {
+            pipeline,
+            screen_buffer,
+            screen_bind_group,
+            texture_layout,
+            srgb: format.describe().srgb,
+            textures: HashMap::new(),
+            vertices: mesh_buffer(device, "Panel Vertices",
+                                  wgpu::BufferUsages::VERTEX, 1 << 16),
+            indices: mesh_buffer(device, "Panel Indices",
+                                 wgpu::BufferUsages::INDEX, 1 << 16)
+        }
+    }
+
+    // Create, patch and free the textures egui asks for
+    pub fn update_textures(&mut self, device: &wgpu::Device,
+                           queue: &wgpu::Queue,
+                           delta: &egui::TexturesDelta) {
+        for (id, image_delta) in &delta.set {
+            let (size, pixels): ([usize; 2], Vec<egui::Color32>) =
+                match &image_delta.image {
+                    egui::ImageData::Color(image) =>
+                        (image.size, image.pixels.clone()),
+                    egui::ImageData::Font(image) =>
+                        (image.size, image.srgba_pixels(1.).collect())
+                };
+            let extent = wgpu::Extent3d {
+                width: size[0] as u32,
+                height: size[1] as u32,
+                depth_or_array_layers: 1
+            };
+            let origin = match image_delta.pos {
+                Some([x, y]) => wgpu::Origin3d {x: x as u32, y: y as u32,
+                                                z: 0},
+                None => {
+                    let texture = self.create_texture(device, extent,
+                                                      image_delta.filter);
+                    self.textures.insert(*id, texture);
+                    wgpu::Origin3d::ZERO
+                }
+            };
+            let texture = match self.textures.get(id) {
+                Some((texture, _)) => texture,
+                None => continue  // patch of an unknown texture
+            };
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture,
+                    mip_level: 0,
+                    origin,
+                    aspect: wgpu::TextureAspect::All
+                },
+                bytemuck::cast_slice(&pixels),
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(4 * extent.width),
+                    rows_per_image: NonZeroU32::new(extent.height)
+                },
+                extent);
+        }
+        for id in &delta.free {
+            self.textures.remove(id);
+        }
+    }
+
+    fn create_texture(&self, device: &wgpu::Device, size: wgpu::Extent3d,
+                      filter: egui::TextureFilter)
+    -> (wgpu::Texture, wgpu::BindGroup)
+    {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Panel Texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING |
+                   wgpu::TextureUsages::COPY_DST
+        });
+        let filter = match filter {
+            egui::TextureFilter::Nearest => wgpu::FilterMode::Nearest,
+            egui::TextureFilter::Linear => wgpu::FilterMode::Linear
+        };
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Panel Sampler"),
+            mag_filter: filter,
+            min_filter: filter,
+            ..Default::default()
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.texture_layout,
+            label: Some("Panel Texture BG"),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view)
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler)
+                }
+            ]
+        });
+        (texture, bind_group)
+    }
+
+    // Record drawing the meshes over view of size pixels
+    #[allow(clippy::too_many_arguments)]
+    pub fn paint(&mut self, device: &wgpu::Device, queue: &wgpu::Queue,
+                 encoder: &mut wgpu::CommandEncoder,
+                 view: &wgpu::TextureView, size: [u32; 2],
+                 pixels_per_point: f32,
+                 primitives: &[egui::ClippedPrimitive]) {
+        let screen = Screen {
+            size: [size[0] as f32 / pixels_per_point,
+                   size[1] as f32 / pixels_per_point],
+            srgb: self.srgb as u32,
+            _padding: 0
+        };
+        queue.write_buffer(&self.screen_buffer, 0,
+                           bytemuck::cast_slice(&[screen]));
+
+        // All meshes are written at once, each draws its byte ranges
+        let meshes: Vec<_> = primitives.iter()
+            .filter_map(|p| match &p.primitive {
+                egui::epaint::Primitive::Mesh(mesh)
+                    if !mesh.indices.is_empty() => Some((p.clip_rect, mesh)),
+                _ => None  // no paint callbacks are used
+            })
+            .collect();
+        let mut vertex_bytes: Vec<u8> = Vec::new();
+        let mut index_bytes: Vec<u8> = Vec::new();
+        let mut ranges = Vec::with_capacity(meshes.len());
+        for (_, mesh) in &meshes {
+            let v = vertex_bytes.len() as u64;
+            let i = index_bytes.len() as u64;
+            vertex_bytes.extend_from_slice(
+                bytemuck::cast_slice(&mesh.vertices));
+            index_bytes.extend_from_slice(bytemuck::cast_slice(&mesh.indices));
+            ranges.push((v .. vertex_bytes.len() as u64,
+                         i .. index_bytes.len() as u64));
+        }
+        if vertex_bytes.len() as u64 > self.vertices.1 {
+            self.vertices = mesh_buffer(
+                device, "Panel Vertices", wgpu::BufferUsages::VERTEX,
+                (vertex_bytes.len() as u64).next_power_of_two());
+        }
+        if index_bytes.len() as u64 > self.indices.1 {
+            self.indices = mesh_buffer(
+                device, "Panel Indices", wgpu::BufferUsages::INDEX,
+                (index_bytes.len() as u64).next_power_of_two());
+        }
+        if meshes.is_empty() {
+            return;
+        }
+        queue.write_buffer(&self.vertices.0, 0, &vertex_bytes);
+        queue.write_buffer(&self.indices.0, 0, &index_bytes);
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Panel Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true
+                }
+            })],
+            depth_stencil_attachment: None
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.screen_bind_group, &[]);
+        for ((clip, mesh), (vertices, indices)) in meshes.iter().zip(ranges) {
+            let bind_group = match self.textures.get(&mesh.texture_id) {
+                Some((_, bind_group)) => bind_group,
+                None => continue
+            };
+            // Clip rectangle in pixels inside the target
+            let x0 = (clip.min.x * pixels_per_point).round()
+                     .clamp(0., size[0] as f32) as u32;
+            let y0 = (clip.min.y * pixels_per_point).round()
+                     .clamp(0., size[1] as f32) as u32;
+            let x1 = (clip.max.x * pixels_per_point).round()
+                     .clamp(x0 as f32, size[0] as f32) as u32;
+            let y1 = (clip.max.y * pixels_per_point).round()
+                     .clamp(y0 as f32, size[1] as f32) as u32;
+            if x1 == x0 || y1 == y0 {
+                continue;
+            }
+            pass.set_scissor_rect(x0, y0, x1 - x0, y1 - y0);
+            pass.set_bind_group(1, bind_group, &[]);
+            pass.set_vertex_buffer(0, self.vertices.0.slice(vertices));
+            pass.set_index_buffer(self.indices.0.slice(indices),
+                                  wgpu::IndexFormat::Uint32);
+            pass.draw_indexed(0 .. mesh.indices.len() as u32, 0, 0 .. 1);
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::{fs, iter, process, sync::Arc};
+
+use physarum_art::gpu_create::device_limits;
+use physarum_art::frame::Frame;
+use physarum_art::{tile::Seams, Simulation};
+
+use crate::cli::Args;
+use crate::load_config;
+
+
+// sRGB like the usual surface formats, so frames look as in the window
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+// Run the simulation without a window and write frames as PNG files
+pub async fn run(args: Args) {
+    let cfg = load_config(&args);
+
+    let instance = wgpu::Instance::new(args.backend.backends());
+    let adapter = match instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            compatible_surface: None,
+            force_fallback_adapter: args.software,
+        })
+        .await {
+        Some(adapter) => adapter,
+        None => {
+            eprintln!("No graphics adapter found for {:?}", args.backend);
+            process::exit(1);
+        }
+    };
+    println!("Adapter: {:?}", adapter.get_info());
+
+    let (device, queue) = adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                features: wgpu::Features::empty(),
+                limits: device_limits()
+            },
+            None, // Trace path
+        )
+        .await
+        .unwrap();
+
+    let device = Arc::new(device);
+    let queue = Arc::new(queue);
+    let mut sim = match Simulation::new(&cfg, device.clone(), queue.clone(),
+                                        FORMAT) {
+        Ok(sim) => sim,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    let (size_x, size_y) = sim.size();
+    let frame = Frame::new(&device, size_x, size_y, FORMAT);
+
+    if let Err(e) = fs::create_dir_all(&args.output) {
+        eprintln!("Could not create output directory {:?}: {}",
+                  args.output, e);
+        process::exit(1);
+    }
+
+    let frame_every = args.frame_every.unwrap_or(args.steps).max(1);
+    for step in 1 ..= args.steps {
+        let ce_desc = wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder")
+        };
+        let mut encoder = device.create_command_encoder(&ce_desc);
+        sim.encode_step(&mut encoder);
+
+        let write_frame = step % frame_every == 0 || step == args.steps;
+        if write_frame {
+            sim.encode_render(&mut encoder, &frame.view);
+            frame.copy_to_buffer(&mut encoder);
+        }
+        queue.submit(iter::once(encoder.finish()));
+
+        if write_frame {
+            let pixels = frame.read_pixels(&device);
+            if args.tileable {
+                check_seams(&pixels, size_x, size_y, step);
+            }
+            let path = format!("{}/frame_{:06}.png", args.output, step);
+            match image::save_buffer(&path, &pixels, size_x, size_y,
+                                     image::ColorType::Rgba8) {
+                Ok(()) => println!("Saved {:?}", path),
+                Err(e) => {
+                    eprintln!("Could not save {:?}: {}", path, e);
+                    process::exit(1);
+                }
+            }
+        }
+    }
+}
+
+// Opposite edges of a tileable frame have to continue each other
+fn check_seams(pixels: &[u8], width: u32, height: u32, step: u32) {
+    let seams = Seams::measure(pixels, width as usize, height as usize, 4);
+    if !seams.is_seamless() {
+        eprintln!("Frame {} is not seamless: {:?}", step, seams);
+        process::exit(1);
+    }
+}
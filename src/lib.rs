@@ -0,0 +1,35 @@
+//! Physarum slime mould simulation on the GPU.
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//! use physarum_art::{Config, Simulation};
+//!
+//! # async fn example(device: wgpu::Device, queue: wgpu::Queue,
+//! #                  view: wgpu::TextureView) {
+//! // device requested with physarum_art::gpu_create::device_limits()
+//! let mut cfg = Config::default();
+//! cfg.size_x = Some(512);
+//! cfg.size_y = Some(512);
+//! let mut sim = Simulation::new(&cfg, Arc::new(device), Arc::new(queue),
+//!                               wgpu::TextureFormat::Rgba8UnormSrgb)
+//!     .expect("nutriment or obstacle image not readable");
+//! sim.step(100);
+//! sim.render_to(&view);
+//! let slime: Vec<f32> = sim.read_slime();
+//! # }
+//! ```
+
+pub mod colormap;
+pub mod config;
+pub mod cpu;
+pub mod frame;
+pub mod gpu_create;
+pub mod maps;
+pub mod post;
+pub mod simulation;
+pub mod sprites;
+pub mod tile;
+pub mod timeline;
+
+pub use config::{Config, ConfigError};
+pub use simulation::Simulation;
@@ -0,0 +1,109 @@
+// Static fields of the grid: nutriment zones and obstacles,
+// generated randomly or read from images.
+use rand::Rng;
+
+use crate::config::{seeded_rng, Config, ConfigError, STREAM_NUTRIMENT};
+use crate::gpu_create::Color;
+
+
+// Nutriment strength and colour of each cell, row major
+pub struct Nutriment {
+    pub strength: Vec<f32>,
+    pub color: Vec<Color>
+}
+
+impl Nutriment {
+    // Image given in the configuration or the random zones
+    pub fn from_config(cfg: &Config, size_x: u32, size_y: u32)
+    -> Result<Nutriment, ConfigError>
+    {
+        match &cfg.nutriment_image {
+            Some(path) => Nutriment::from_image(path, size_x, size_y),
+            None => Ok(Nutriment::random_zones(cfg, size_x, size_y))
+        }
+    }
+
+    // n_fix discs with a random colour, strongest in the center
+    pub fn random_zones(cfg: &Config, size_x: u32, size_y: u32) -> Nutriment {
+        let xy_size = (size_x * size_y) as usize;
+        let mut rng = seeded_rng(cfg.seed.unwrap_or(0), STREAM_NUTRIMENT);
+        let mut strength: Vec<f32> = vec![0.; xy_size];
+        let mut color: Vec<Color> = vec![Color {r: 1., g: 1., b: 1.}; xy_size];
+        for _ in 0 .. cfg.n_fix {
+            let radius: u32 = rng.gen_range(cfg.r_fix_min .. cfg.r_fix_max);
+            let c_x: u32 = rng.gen_range(radius .. size_x - radius);
+            let c_y: u32 = rng.gen_range(radius .. size_y - radius);
+            let r = rng.gen_range(0. .. 1.);
+            let g = rng.gen_range(0. .. 1.);
+            let b = rng.gen_range(0. .. 1.);
+
+            for x in c_x-radius .. c_x+radius {
+                for y in c_y-radius .. c_y+radius {
+                    let idx = (x + y*size_x) as usize;
+                    let vx: f32 = x as f32 - c_x as f32;
+                    let vy: f32 = y as f32 - c_y as f32;
+                    strength[idx] =
+                        (1. - (vx.powf(2.) + vy.powf(2.)).sqrt() / radius as f32
+                         ).max(0.);
+                    if strength[idx] > 0. {
+                        color[idx] = Color {r, g , b};
+                    }
+                }}
+        }
+        Nutriment {strength, color}
+    }
+
+    // Luminance is the strength, the pixel colour the nutriment colour
+    pub fn from_image(path: &str, size_x: u32, size_y: u32)
+    -> Result<Nutriment, ConfigError>
+    {
+        let image = load_image(path, size_x, size_y)?;
+        let mut strength = Vec::with_capacity((size_x * size_y) as usize);
+        let mut color = Vec::with_capacity((size_x * size_y) as usize);
+        for pixel in image.pixels() {
+            let [r, g, b, a] = pixel.0.map(|c| c as f32 / 255.);
+            strength.push(luminance(r, g, b) * a);
+            color.push(Color {r, g, b});
+        }
+        Ok(Nutriment {strength, color})
+    }
+}
+
+// Walls from the image given in the configuration, 1 for a wall cell
+pub fn obstacles_from_config(cfg: &Config, size_x: u32, size_y: u32)
+-> Result<Vec<u32>, ConfigError>
+{
+    match &cfg.obstacle_image {
+        Some(path) => obstacles_from_image(path, size_x, size_y),
+        None => Ok(vec![0; (size_x * size_y) as usize])
+    }
+}
+
+// Opaque pixels brighter than half are walls
+pub fn obstacles_from_image(path: &str, size_x: u32, size_y: u32)
+-> Result<Vec<u32>, ConfigError>
+{
+    let image = load_image(path, size_x, size_y)?;
+    Ok(image.pixels()
+        .map(|pixel| {
+            let [r, g, b, a] = pixel.0.map(|c| c as f32 / 255.);
+            (luminance(r, g, b) * a >= 0.5) as u32
+        })
+        .collect())
+}
+
+// Rec. 709 luma of the stored values
+fn luminance(r: f32, g: f32, b: f32) -> f32 {
+    0.2126*r + 0.7152*g + 0.0722*b
+}
+
+// Image stretched to the grid, the first row is the top of the window
+pub(crate) fn load_image(path: &str, size_x: u32, size_y: u32)
+-> Result<image::RgbaImage, ConfigError>
+{
+    let image = image::open(path)
+        .map_err(|e| ConfigError::Image(path.to_string(), e))?;
+    Ok(image.resize_exact(size_x, size_y,
+                          image::imageops::FilterType::Triangle)
+            .to_rgba8())
+}
@@ -0,0 +1,223 @@
+// Offscreen target of the trail rendering and the post processing chain
+// drawing it into the output: bloom, vignette and film grain.
+use bytemuck::{Pod, Zeroable};
+
+use crate::config::{Boundary, Config};
+
+
+// Float target, the chain works on linear values and the output
+// format encodes them
+pub const SCENE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct PostParams {
+    pub bloom: f32,  // 0: the blur passes are skipped
+    pub threshold: f32,
+    pub radius: f32,
+    pub vignette: f32,
+    pub grain: f32,
+    pub frame: u32,  // seed of the grain
+    pub wrap: u32,  // 1: the blur continues at the opposite edge
+    _padding: u32
+}
+
+unsafe impl Zeroable for PostParams {}
+unsafe impl Pod for PostParams {}
+
+impl PostParams {
+    pub fn new(cfg: &Config, frame: u32) -> Self {
+        let wrap = cfg.boundary == Boundary::Wrap;
+        PostParams {
+            bloom: cfg.bloom,
+            threshold: cfg.bloom_threshold,
+            radius: cfg.bloom_radius,
+            // a torus has no corners, tiles stay seamless
+            vignette: if wrap { 0. } else { cfg.vignette },
+            grain: cfg.grain,
+            frame,
+            wrap: wrap as u32,
+            _padding: 0
+        }
+    }
+}
+
+pub struct PostProcess {
+    // render target of the trails
+    pub scene: wgpu::TextureView,
+    params_buffer: wgpu::Buffer,
+    blur_h: wgpu::RenderPipeline,
+    blur_v: wgpu::RenderPipeline,
+    composite: wgpu::RenderPipeline,
+    bind_group_h: wgpu::BindGroup,
+    bind_group_v: wgpu::BindGroup,
+    bind_group_composite: wgpu::BindGroup,
+    // glow after the horizontal and after both blur passes
+    glow_h: wgpu::TextureView,
+    glow: wgpu::TextureView
+}
+
+impl PostProcess {
+    // Scene and glow of the grid size, format of the output
+    pub fn new(device: &wgpu::Device, size_x: u32, size_y: u32,
+               format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(
+            wgpu::include_wgsl!("../Shader/post.wgsl"));
+
+        let create_target = |label| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: size_x,
+                    height: size_y,
+                    depth_or_array_layers: 1
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: SCENE_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT |
+                       wgpu::TextureUsages::TEXTURE_BINDING
+            }).create_view(&wgpu::TextureViewDescriptor::default())
+        };
+        let scene = create_target("Scene");
+        let glow_h = create_target("Glow Horizontal");
+        let glow = create_target("Glow");
+
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Post Params"),
+            size: std::mem::size_of::<PostParams>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false
+        });
+
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float {
+                    filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false },
+            count: None
+        };
+        let layout = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None },
+                        count: None
+                    },
+                    texture_entry(1),
+                    texture_entry(2)
+                ],
+                label: Some("Post Layout")
+            });
+        // glow is unused by the blur passes, any other than the target
+        let create_bind_group = |label, source, glow| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &layout,
+                label: Some(label),
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: params_buffer.as_entire_binding()
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(source)
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(glow)
+                    }
+                ]
+            })
+        };
+        let bind_group_h = create_bind_group("Blur H BG", &scene, &glow);
+        let bind_group_v = create_bind_group("Blur V BG", &glow_h, &scene);
+        let bind_group_composite = create_bind_group("Composite BG", &scene,
+                                                     &glow);
+
+        let pipeline_layout = device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Post"),
+                bind_group_layouts: &[&layout],
+                push_constant_ranges: &[]
+            });
+        let create_pipeline = |entry_point, format| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[]
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL
+                    })]
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None
+            })
+        };
+
+        Self {
+            scene,
+            params_buffer,
+            blur_h: create_pipeline("blur_h", SCENE_FORMAT),
+            blur_v: create_pipeline("blur_v", SCENE_FORMAT),
+            composite: create_pipeline("composite", format),
+            bind_group_h,
+            bind_group_v,
+            bind_group_composite,
+            glow_h,
+            glow
+        }
+    }
+
+    // Record the chain from the scene into view
+    pub fn encode(&self, queue: &wgpu::Queue,
+                  encoder: &mut wgpu::CommandEncoder,
+                  view: &wgpu::TextureView, params: PostParams) {
+        queue.write_buffer(&self.params_buffer, 0,
+                           bytemuck::cast_slice(&[params]));
+
+        let blur = [(&self.blur_h, &self.bind_group_h, &self.glow_h),
+                    (&self.blur_v, &self.bind_group_v, &self.glow)];
+        let composite = [(&self.composite, &self.bind_group_composite, view)];
+        let blur = if params.bloom > 0. { &blur[..] } else { &[] };
+        for &(pipeline, bind_group, target) in blur.iter().chain(&composite) {
+            let mut pass = encoder.begin_render_pass(
+                &wgpu::RenderPassDescriptor {
+                    label: Some("Post Pass"),
+                    color_attachments: &[Some(
+                        wgpu::RenderPassColorAttachment {
+                            view: target,
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                                store: true
+                            }
+                        })],
+                    depth_stencil_attachment: None
+                });
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, bind_group, &[]);
+            pass.draw(0 .. 3, 0 .. 1);
+        }
+    }
+}
@@ -0,0 +1,875 @@
+use wgpu::util::DeviceExt;
+use rand::Rng;
+use std::{f32::consts::PI, iter, sync::{mpsc, Arc}};
+
+use crate::config::{seeded_rng, Config, ConfigError, Species, MAX_SPECIES,
+                    STREAM_AGENTS};
+use crate::gpu_create::{create_physarum_bind_group,
+                        create_slime_bind_group,
+                        create_nutriment_bind_group,
+                        create_bind_group_layout_compute_agents,
+                        create_bind_group_layout_compute_slime,
+                        create_bind_group_layout_compute_nutriment,
+                        create_render_bind_group,
+                        create_bind_group_layout_render,
+                        create_compute_pipeline, create_pipeline_layout,
+                        deposit_scale, Agent, SpeciesParams, Uniforms,
+                        Vertex, Color};
+use crate::maps::{obstacles_from_config, Nutriment};
+use crate::post::{PostParams, PostProcess, SCENE_FORMAT};
+use crate::sprites::{AgentSprites, SpriteParams};
+use crate::timeline::at_step;
+
+
+// GPU buffers and pipelines of a simulation, independent of the output
+pub struct Simulation {
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    cfg: Config,
+    // Parameters of the next step, updated every step with a timeline
+    uniforms: Uniforms,
+    uniform_buffer: wgpu::Buffer,
+    species_buffer: wgpu::Buffer,
+    steps: u32,
+    render_pipeline: wgpu::RenderPipeline,
+    post: PostProcess,
+    sprites: AgentSprites,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    species: Vec<SpeciesParams>,
+    // Ping-pong buffers, index parity holds the current slime field
+    // and nutriment
+    slime: [wgpu::Buffer; 2],
+    slime_size: wgpu::BufferAddress,
+    parity: usize,
+    agents: wgpu::Buffer,
+    nutriment: [wgpu::Buffer; 2],
+    nutriment_capacity: wgpu::Buffer,
+    obstacles: wgpu::Buffer,
+    color_slime: wgpu::Buffer,
+    color_nutriment: wgpu::Buffer,
+    colormap: wgpu::Buffer,
+    bind_groups_physarum: [wgpu::BindGroup; 2],
+    bind_groups_slime: [wgpu::BindGroup; 2],
+    bind_groups_nutriment: [wgpu::BindGroup; 2],
+    bind_groups_r: [wgpu::BindGroup; 2],
+    compute_physarum: wgpu::ComputePipeline,
+    compute_slime: wgpu::ComputePipeline,
+    compute_nutriment: wgpu::ComputePipeline
+}
+
+
+// The vertices that make up the rectangle to which the image will be drawn.
+const VERTICES: &[Vertex] = &[
+    Vertex {
+        position: [-1.0, -1.0],
+    },
+    Vertex {
+        position: [1.0, -1.0],
+    },
+    Vertex {
+        position: [1.0, 1.0],
+    },
+    Vertex {
+        position: [-1.0, 1.0],
+    },
+];
+const INDICES: &[u16] = &[0, 1, 2, 0, 2, 3];
+
+// Workgroup size of the compute shaders
+const WORKGROUP_SIZE: u32 = 256;
+// Default limit of workgroups per dispatch dimension
+const MAX_WORKGROUPS: u32 = 65535;
+// Start positions drawn in the disc before taking any free cell
+const START_TRIES: u32 = 16;
+
+// Workgroups for one invocation per item, the shaders compute the item
+// index as gId.x + gId.y * x * WORKGROUP_SIZE and skip the remainder
+fn workgroups(n: u32) -> (u32, u32) {
+    let groups = n.div_ceil(WORKGROUP_SIZE);
+    if groups <= MAX_WORKGROUPS {
+        (groups.max(1), 1)
+    } else {
+        (MAX_WORKGROUPS, groups.div_ceil(MAX_WORKGROUPS))
+    }
+}
+
+// Largest buffer bound to a shader
+fn max_buffer(limits: &wgpu::Limits) -> u64 {
+    limits.max_buffer_size.min(limits.max_storage_buffer_binding_size as u64)
+}
+
+// Largest size_x and size_y of the device, their product is limited by the
+// buffer size as well
+pub fn max_grid_size(limits: &wgpu::Limits) -> u32 {
+    limits.max_texture_dimension_2d
+}
+
+// Most agents the device holds
+pub fn max_agents(limits: &wgpu::Limits) -> u32 {
+    (max_buffer(limits) / std::mem::size_of::<Agent>() as u64)
+        .min(u32::MAX as u64) as u32
+}
+
+// Grid textures and the largest buffers have to fit into the device
+fn check_limits(limits: &wgpu::Limits, size_x: u32, size_y: u32,
+                n_agents: u32, n_channels: usize) -> Result<(), ConfigError> {
+    let mut errors = Vec::new();
+    let max_size = max_grid_size(limits);
+    for (name, size) in [("size_x", size_x), ("size_y", size_y)] {
+        if size > max_size {
+            errors.push(format!("{}: {} exceeds the device limit of {}",
+                                name, size, max_size));
+        }
+    }
+    let max_buffer = max_buffer(limits);
+    let cells = size_x as u64 * size_y as u64;
+    let field = cells * (n_channels * std::mem::size_of::<f32>())
+        .max(std::mem::size_of::<Color>()) as u64;
+    if field > max_buffer {
+        errors.push(format!(
+            "size_x, size_y: {} x {} needs buffers of {} bytes, \
+             the device limit is {}", size_x, size_y, field, max_buffer));
+    }
+    if n_agents > max_agents(limits) {
+        let agents = n_agents as u64 * std::mem::size_of::<Agent>() as u64;
+        errors.push(format!(
+            "n_agents: {} needs a buffer of {} bytes, the device limit is {}",
+            n_agents, agents, max_buffer));
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(ConfigError::Invalid(errors))
+    }
+}
+
+// GPU parameters of each species, values unset in a species are taken
+// from the top level of the configuration
+fn species_params(cfg: &Config) -> Vec<SpeciesParams> {
+    let single = [Species::default()];
+    let species = if cfg.species.is_empty() {
+        &single[..]
+    } else {
+        &cfg.species[..]
+    };
+
+    species.iter().enumerate().map(|(s, sp)| {
+        let mut interaction = [0.; MAX_SPECIES];
+        let mut repulsion = [0.; MAX_SPECIES];
+        for t in 0 .. species.len() {
+            interaction[t] = sp.interaction_with(s, t);
+            repulsion[t] = sp.repulsion_from(t);
+        }
+        SpeciesParams {
+            v: sp.v.unwrap_or(cfg.v),
+            deposit: sp.deposit.unwrap_or(cfg.deposit),
+            repellent: sp.repellent.unwrap_or(cfg.repellent),
+            phi_sens: sp.phi_sens.unwrap_or(cfg.phi_sens),
+            turn_speed: sp.turn_speed.unwrap_or(cfg.turn_speed),
+            w_nutriment: sp.w_nutriment.unwrap_or(cfg.w_nutriment),
+            color: Color {r: sp.color[0], g: sp.color[1], b: sp.color[2]},
+            interaction,
+            repulsion
+        }
+    }).collect()
+}
+
+// Shader parameters of the configuration with the given trail channels
+fn uniforms(cfg: &Config, n_species: usize, n_channels: usize) -> Uniforms {
+    Uniforms {n_agents: cfg.n_agents.unwrap(),
+              size_x: cfg.size_x.unwrap(),
+              size_y: cfg.size_y.unwrap(),
+              n_species: n_species as u32,
+              n_channels: n_channels as u32,
+              decay: cfg.decay,
+              repellent_decay: cfg.repellent_decay,
+              sens_range_min: cfg.sens_range_min,
+              sens_range_max: cfg.sens_range_max,
+              sense_steps: cfg.sens_range_max - cfg.sens_range_min + 1.,
+              seed: cfg.shader_seed.unwrap(),
+              deposit_mode: cfg.deposit_mode as u32,
+              boundary: cfg.boundary as u32,
+              color_sampling: cfg.color_sampling as u32,
+              color_blend: cfg.color_blend,
+              consumption: cfg.consumption,
+              regrowth: cfg.regrowth,
+              nutriment_diffusion: cfg.nutriment_diffusion,
+              colormap: !cfg.colormap.is_species() as u32,
+              tone_mapping: cfg.tone_mapping as u32,
+              exposure: cfg.exposure,
+              gamma: cfg.gamma,
+              white: cfg.white,
+              nutriment_opacity: cfg.layers.nutriment.shown_opacity(),
+              nutriment_blend: cfg.layers.nutriment.blend as u32,
+              trails_opacity: cfg.layers.trails.shown_opacity(),
+              trails_blend: cfg.layers.trails.blend as u32,
+              obstacles_opacity: cfg.layers.obstacles.shown_opacity(),
+              obstacles_blend: cfg.layers.obstacles.blend as u32,
+              // validated to fit
+              deposit_scale: deposit_scale(cfg.n_agents.unwrap(),
+                                           cfg.max_deposit())
+                  .unwrap_or(1.)}
+}
+
+impl Simulation {
+    // Run setup values missing in cfg are completed with their defaults,
+    // format is the format of the texture rendered to.
+    // Fails if an image of the configuration can't be read or the grid or
+    // the agents exceed the limits of the device.
+    pub fn new(cfg: &Config, device: Arc<wgpu::Device>,
+               queue: Arc<wgpu::Queue>, format: wgpu::TextureFormat)
+    -> Result<Self, ConfigError>
+    {
+        let mut cfg = cfg.clone();
+        cfg.complete();
+        let seed = cfg.seed.unwrap();
+        let size_x = cfg.size_x.unwrap();
+        let size_y = cfg.size_y.unwrap();
+        let n_agents = cfg.n_agents.unwrap() as usize;
+        // Parameters of the first step
+        let cfg_0 = at_step(&cfg, 0);
+        let species = species_params(&cfg_0);
+        let n_species = species.len();
+        // Repellent trails only if deposited at all, now or later
+        let n_channels = if species.iter().any(|sp| sp.repellent > 0.) ||
+                            cfg.timeline.iter()
+                               .any(|k| k.repellent.unwrap_or(0.) > 0.) {
+            2 * n_species
+        } else {
+            n_species
+        };
+        check_limits(&device.limits(), size_x, size_y, cfg.n_agents.unwrap(),
+                     n_channels)?;
+        let nutriment_init = Nutriment::from_config(&cfg, size_x, size_y)?;
+        let obstacles_init = obstacles_from_config(&cfg, size_x, size_y)?;
+        let lut = cfg.colormap.lut()?;
+
+        // Compute pipeline
+        let cs_desc = wgpu::include_wgsl!("../Shader/Physarum.wgsl");
+        let cs_mod = device.create_shader_module(cs_desc);
+        let cs_slime_di_desc = wgpu::include_wgsl!("../Shader/Slime.wgsl");
+        let cs_slime_di_mod = device.create_shader_module(cs_slime_di_desc);
+        let cs_nutriment_desc = wgpu::include_wgsl!("../Shader/Nutriment.wgsl");
+        let cs_nutriment_mod = device.create_shader_module(cs_nutriment_desc);
+        // Buffer for physarum agents
+        // x, y, phi, 3*sensor (bool) as u32 since bool not supported
+        let mut rng = seeded_rng(seed, STREAM_AGENTS);
+        let mut agents_init: Vec<Agent> = Vec::with_capacity(n_agents);
+        let c_x = size_x as f32 * 0.5;
+        let c_y = size_y as f32 * 0.5;
+        // Cells agents can start on when there are walls
+        let free: Vec<usize> = if obstacles_init.contains(&1) {
+            let free: Vec<usize> = (0 .. obstacles_init.len())
+                .filter(|&c| obstacles_init[c] == 0).collect();
+            if free.is_empty() {
+                return Err(ConfigError::Invalid(vec![
+                    "obstacle_image: no free cell for the agents"
+                        .to_string()]));
+            }
+            free
+        } else {
+            Vec::new()
+        };
+        let in_wall = |x: f32, y: f32| {
+            !free.is_empty() &&
+            (x < 0. || y < 0. || x >= size_x as f32 || y >= size_y as f32 ||
+             obstacles_init[x as usize + y as usize * size_x as usize] != 0)
+        };
+        for i in 0 .. n_agents {
+            // start positions in walls are drawn again, a free cell is
+            // taken when the disc keeps hitting walls
+            let (mut x, mut y) = (f32::NAN, f32::NAN);
+            for _ in 0 .. START_TRIES {
+                let r = rng.gen_range(0. .. cfg.r_init);
+                let phi = rng.gen_range(0. .. 2.*PI);
+                x = c_x + r*f32::cos(phi);
+                y = c_y + r*f32::sin(phi);
+                if !in_wall(x, y) {
+                    break;
+                }
+            }
+            if in_wall(x, y) {
+                let cell = free[rng.gen_range(0 .. free.len())];
+                x = (cell % size_x as usize) as f32 + 0.5;
+                y = (cell / size_x as usize) as f32 + 0.5;
+            }
+            agents_init.push(
+                Agent{
+                    x,
+                    y,
+                    phi: rng.gen_range(0. .. 2.*PI),
+                    species: (i % n_species) as u32,
+                    color: Color {r: 1., g: 1., b: 1.}
+                }
+            );
+        }
+        let agents = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Physarum Agents"),
+                contents: bytemuck::cast_slice::<_, u8>(&agents_init),
+                usage:  wgpu::BufferUsages::STORAGE |
+                        wgpu::BufferUsages::VERTEX |
+                        wgpu::BufferUsages::COPY_SRC,
+            });
+
+        let species_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Species"),
+                contents: bytemuck::cast_slice::<_, u8>(&species),
+                usage:  wgpu::BufferUsages::STORAGE |
+                        wgpu::BufferUsages::COPY_DST
+            });
+
+        // Buffer for slime concentration, trails and repellent trails
+        let xy_size = (size_x * size_y) as usize;
+        let slime_size = (n_channels * xy_size * std::mem::size_of::<f32>())
+                        as wgpu::BufferAddress;
+        let create_slime_buffer = |label| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: slime_size,
+                usage:  wgpu::BufferUsages::STORAGE |
+                        wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            })
+        };
+        let slime = [create_slime_buffer("SLIME 0"),
+                     create_slime_buffer("SLIME 1")];
+        // Deposits of the agents, added and cleared by the slime pass
+        let deposit = [create_slime_buffer("Deposit 0"),
+                       create_slime_buffer("Deposit 1")];
+        // Agents passing each cell, counted by the agents and
+        // cleared by the nutriment pass after eating
+        let visits = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Visits"),
+            size: (xy_size * std::mem::size_of::<u32>())
+                  as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        // Fixed slime zones -> nutriment
+        let color_slime_init: Vec<Color> =
+            vec![Color {r: 1., g: 1., b: 1.}; xy_size];
+        // eaten by the agents, regrowing towards the initial capacity
+        let create_nutriment_buffer = |label| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents: bytemuck::cast_slice::<_, u8>(
+                    &nutriment_init.strength),
+                usage:  wgpu::BufferUsages::STORAGE |
+                        wgpu::BufferUsages::COPY_SRC,
+            })
+        };
+        let nutriment = [create_nutriment_buffer("Nutriment 0"),
+                         create_nutriment_buffer("Nutriment 1")];
+        let nutriment_capacity = create_nutriment_buffer("Nutriment Capacity");
+        // Walls
+        let obstacles = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Obstacles"),
+                contents: bytemuck::cast_slice::<_, u8>(&obstacles_init),
+                usage:  wgpu::BufferUsages::STORAGE |
+                        wgpu::BufferUsages::COPY_SRC,
+            });
+        // Color buffer
+        let color_slime = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Color SLIME Src"),
+                contents: bytemuck::cast_slice::<_, u8>(&color_slime_init),
+                usage:  wgpu::BufferUsages::STORAGE |
+                        wgpu::BufferUsages::COPY_SRC
+            });
+        let color_nutriment = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Color SLIME Dst"),
+                contents: bytemuck::cast_slice::<_, u8>(&nutriment_init.color),
+                usage:  wgpu::BufferUsages::STORAGE |
+                        wgpu::BufferUsages::COPY_SRC
+            });
+
+        // Buffer for parameter
+        let uniforms = vec![uniforms(&cfg_0, n_species, n_channels)];
+        let usage = wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST;
+        let uniform_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("uniform-buffer"),
+                contents: bytemuck::cast_slice::<_, u8>(&uniforms),
+                usage,
+            });
+
+        // Compute Pipelines //
+        //____________________//
+        // Physarum
+        let bind_group_layout_physarum =
+            create_bind_group_layout_compute_agents(&device);
+        let bind_groups_physarum = [0, 1].map(|p| create_physarum_bind_group(
+            &device,
+            &bind_group_layout_physarum,
+            &agents,
+            &slime[p],
+            &deposit[p],
+            &nutriment[p],
+            &uniform_buffer,
+            &obstacles,
+            &color_slime,
+            &color_nutriment,
+            &species_buffer,
+            &visits
+        ));
+        let pipeline_layout_physarum = create_pipeline_layout(
+            &device, &bind_group_layout_physarum, "Physarum Compute");
+        let physarum_pipeline = create_compute_pipeline(&device,
+                                                        &pipeline_layout_physarum,
+                                                        &cs_mod,
+                                                        "Physarum Pipeline");
+        // Slime
+        // dissipation and decay
+        let bind_group_layout_slime =
+            create_bind_group_layout_compute_slime(&device);
+        let bind_groups_slime = [0, 1].map(|p| create_slime_bind_group(
+            &device,
+            &bind_group_layout_slime,
+            &slime[p],
+            &slime[1 - p],
+            &uniform_buffer,
+            &deposit[p],
+            &deposit[1 - p],
+            &obstacles
+        ));
+        let pipeline_layout_slime = create_pipeline_layout(
+            &device, &bind_group_layout_slime, "Slime Layout");
+        let slime_pipeline = create_compute_pipeline(
+            &device, &pipeline_layout_slime, &cs_slime_di_mod,
+            "Slime dissipation Pipeline");
+
+        // Nutriment
+        // consumption, diffusion and regrowth
+        let bind_group_layout_nutriment =
+            create_bind_group_layout_compute_nutriment(&device);
+        let bind_groups_nutriment = [0, 1].map(|p| create_nutriment_bind_group(
+            &device,
+            &bind_group_layout_nutriment,
+            &nutriment[p],
+            &nutriment[1 - p],
+            &uniform_buffer,
+            &visits,
+            &nutriment_capacity,
+            &obstacles
+        ));
+        let pipeline_layout_nutriment = create_pipeline_layout(
+            &device, &bind_group_layout_nutriment, "Nutriment Layout");
+        let nutriment_pipeline = create_compute_pipeline(
+            &device, &pipeline_layout_nutriment, &cs_nutriment_mod,
+            "Nutriment Pipeline");
+
+        // Lookup table of the colour map
+        let colormap = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Colormap"),
+                contents: bytemuck::cast_slice::<_, u8>(&lut),
+                usage:  wgpu::BufferUsages::STORAGE |
+                        wgpu::BufferUsages::COPY_DST
+            });
+
+        // Shader for Render Pipeline
+        let vs_desc = wgpu::include_wgsl!("../Shader/passThrough.wgsl");
+        let vs_mod = device.create_shader_module(vs_desc);
+        let fs_desc = wgpu::include_wgsl!("../Shader/render.wgsl");
+        let fs_mod = device.create_shader_module(fs_desc);
+
+        let bind_group_layout_r = create_bind_group_layout_render(&device);
+        let bind_groups_r = [0, 1].map(|p| create_render_bind_group(
+            &device,
+            &bind_group_layout_r,
+            &slime[p],
+            &uniform_buffer,
+            &color_slime,
+            &species_buffer,
+            &colormap,
+            &nutriment[p],
+            &color_nutriment,
+            &obstacles
+        ));
+        let pipeline_layout_r = create_pipeline_layout(
+            &device,
+            &bind_group_layout_r,
+            "Physarum Render");
+
+        let render_pipeline = device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("Render Pipeline"),
+                layout: Some(&pipeline_layout_r),
+                vertex: wgpu::VertexState {
+                    module: &vs_mod,
+                    entry_point: "main",
+                    buffers: &[Vertex::desc()],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &fs_mod,
+                    entry_point: "main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: SCENE_FORMAT,
+                        blend: Some(wgpu::BlendState {
+                            color: wgpu::BlendComponent::REPLACE,
+                            alpha: wgpu::BlendComponent::REPLACE,
+                        }),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: Some(wgpu::Face::Back),
+                    // Setting this to anything other than Fill requires Features::POLYGON_MODE_LINE
+                    // or Features::POLYGON_MODE_POINT
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    // Requires Features::DEPTH_CLIP_CONTROL
+                    unclipped_depth: false,
+                    // Requires Features::CONSERVATIVE_RASTERIZATION
+                    conservative: false,
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                // If the pipeline will be used with a multiview render pass, this
+                // indicates how many array layers the attachments will have.
+                multiview: None,
+            });
+
+        let post = PostProcess::new(&device, size_x, size_y, format);
+        let sprites = AgentSprites::new(&device);
+
+        let vertex_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Vertex Buffer"),
+                contents: bytemuck::cast_slice(VERTICES),
+                usage: wgpu::BufferUsages::VERTEX,
+            }
+        );
+        let index_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Index Buffer"),
+                contents: bytemuck::cast_slice(INDICES),
+                usage: wgpu::BufferUsages::INDEX,
+            }
+        );
+
+        Ok(Self {
+            device,
+            queue,
+            cfg,
+            uniforms: uniforms[0],
+            uniform_buffer,
+            species_buffer,
+            steps: 0,
+            species,
+            render_pipeline,
+            post,
+            sprites,
+            vertex_buffer,
+            index_buffer,
+            slime,
+            slime_size,
+            parity: 0,
+            agents,
+            nutriment,
+            nutriment_capacity,
+            obstacles,
+            color_slime,
+            color_nutriment,
+            colormap,
+            bind_groups_physarum,
+            bind_groups_slime,
+            bind_groups_nutriment,
+            bind_groups_r,
+            compute_physarum: physarum_pipeline,
+            compute_slime: slime_pipeline,
+            compute_nutriment: nutriment_pipeline
+        })
+    }
+
+    // Effective configuration with the completed run setup
+    pub fn config(&self) -> &Config {
+        &self.cfg
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.cfg.size_x.unwrap(), self.cfg.size_y.unwrap())
+    }
+
+    pub fn n_agents(&self) -> u32 {
+        self.cfg.n_agents.unwrap()
+    }
+
+    // Steps simulated so far
+    pub fn steps(&self) -> u32 {
+        self.steps
+    }
+
+    // Parameter as seen by the shaders in the next step
+    pub fn uniforms(&self) -> Uniforms {
+        self.uniforms
+    }
+
+    // Resolved parameters of each species in the next step
+    pub fn species(&self) -> &[SpeciesParams] {
+        &self.species
+    }
+
+    // Run n simulation steps
+    pub fn step(&mut self, n: u32) {
+        if self.cfg.timeline.is_empty() {
+            let mut encoder = self.create_encoder();
+            for _ in 0 .. n {
+                self.encode_step(&mut encoder);
+            }
+            self.queue.submit(iter::once(encoder.finish()));
+        } else {
+            // the parameters of each step are written before its submission
+            for _ in 0 .. n {
+                let mut encoder = self.create_encoder();
+                self.encode_step(&mut encoder);
+                self.queue.submit(iter::once(encoder.finish()));
+            }
+        }
+    }
+
+    // Draw the slime field into view, which has the format passed to new
+    pub fn render_to(&self, view: &wgpu::TextureView) {
+        let mut encoder = self.create_encoder();
+        self.encode_render(&mut encoder, view);
+        self.queue.submit(iter::once(encoder.finish()));
+    }
+
+    // Slime concentration, row major with size_x columns,
+    // the trail of species s starts at s * size_x * size_y
+    // followed by the repellent trails if any
+    pub fn read_slime(&self) -> Vec<f32> {
+        self.read_buffer(&self.slime[self.parity], self.slime_size)
+    }
+
+    pub fn read_slime_color(&self) -> Vec<Color> {
+        let (size_x, size_y) = self.size();
+        let size = (size_x * size_y) as usize * std::mem::size_of::<Color>();
+        self.read_buffer(&self.color_slime, size as wgpu::BufferAddress)
+    }
+
+    // Nutriment left after consumption and regrowth
+    pub fn read_nutriment(&self) -> Vec<f32> {
+        let (size_x, size_y) = self.size();
+        let size = (size_x * size_y) as usize * std::mem::size_of::<f32>();
+        self.read_buffer(&self.nutriment[self.parity],
+                         size as wgpu::BufferAddress)
+    }
+
+    // Initial nutriment, the level it grows back to
+    pub fn read_nutriment_capacity(&self) -> Vec<f32> {
+        let (size_x, size_y) = self.size();
+        let size = (size_x * size_y) as usize * std::mem::size_of::<f32>();
+        self.read_buffer(&self.nutriment_capacity,
+                         size as wgpu::BufferAddress)
+    }
+
+    pub fn read_agents(&self) -> Vec<Agent> {
+        let size = self.n_agents() as usize * std::mem::size_of::<Agent>();
+        self.read_buffer(&self.agents, size as wgpu::BufferAddress)
+    }
+
+    pub fn read_agent_colors(&self) -> Vec<Color> {
+        self.read_agents().iter().map(|a| a.color).collect()
+    }
+
+    pub fn read_nutriment_color(&self) -> Vec<Color> {
+        let (size_x, size_y) = self.size();
+        let size = (size_x * size_y) as usize * std::mem::size_of::<Color>();
+        self.read_buffer(&self.color_nutriment, size as wgpu::BufferAddress)
+    }
+
+    // 1 for wall cells
+    pub fn read_obstacles(&self) -> Vec<u32> {
+        let (size_x, size_y) = self.size();
+        let size = (size_x * size_y) as usize * std::mem::size_of::<u32>();
+        self.read_buffer(&self.obstacles, size as wgpu::BufferAddress)
+    }
+
+    // Record one simulation step: agent movement, slime dissipation
+    // and nutriment consumption. With a timeline the parameters of the step
+    // are written to the queue, submit the encoder before the next step.
+    pub fn encode_step(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if !self.cfg.timeline.is_empty() {
+            self.write_parameters();
+        }
+        let p = self.parity;
+        // Compute pass
+        {
+            let c_p_pass_desc = wgpu::ComputePassDescriptor {
+                label: Some("Physarum Compute Pass")
+            };
+            let mut c_p_pass = encoder.begin_compute_pass(&c_p_pass_desc);
+            c_p_pass.set_pipeline(&self.compute_physarum);
+            c_p_pass.set_bind_group(0, &self.bind_groups_physarum[p], &[]);
+            let (x, y) = workgroups(self.n_agents());
+            c_p_pass.dispatch_workgroups(x, y, 1);
+        }
+        {
+            let c_s_pass_desc = wgpu::ComputePassDescriptor {
+                label: Some("Slime Pass")
+            };
+            let mut c_s_pass = encoder.begin_compute_pass(&c_s_pass_desc);
+            c_s_pass.set_pipeline(&self.compute_slime);
+            c_s_pass.set_bind_group(0, &self.bind_groups_slime[p], &[]);
+            let (size_x, size_y) = self.size();
+            let n_channels = self.uniforms.n_channels;
+            let (x, y) = workgroups(size_x * size_y * n_channels);
+            c_s_pass.dispatch_workgroups(x, y, 1);
+        }
+        {
+            let c_n_pass_desc = wgpu::ComputePassDescriptor {
+                label: Some("Nutriment Pass")
+            };
+            let mut c_n_pass = encoder.begin_compute_pass(&c_n_pass_desc);
+            c_n_pass.set_pipeline(&self.compute_nutriment);
+            c_n_pass.set_bind_group(0, &self.bind_groups_nutriment[p], &[]);
+            let (size_x, size_y) = self.size();
+            let (x, y) = workgroups(size_x * size_y);
+            c_n_pass.dispatch_workgroups(x, y, 1);
+        }
+
+        // The slime and nutriment passes wrote the fields for the next step
+        self.parity = 1 - p;
+        self.steps += 1;
+        if !self.cfg.timeline.is_empty() {
+            self.update_parameters();
+        }
+    }
+
+    // Take over the parameters of cfg from the next step on.
+    // The run setup, the maps, the start of the agents and the number of
+    // species and trail channels only change with a new simulation.
+    // Fails without changes if the colour map image can't be read.
+    pub fn set_parameters(&mut self, cfg: &Config) -> Result<(), ConfigError> {
+        if cfg.colormap != self.cfg.colormap {
+            let lut = cfg.colormap.lut()?;
+            self.queue.write_buffer(&self.colormap, 0,
+                                    bytemuck::cast_slice(&lut));
+        }
+        let mut cfg = cfg.clone();
+        let current = &self.cfg;
+        cfg.size_x = current.size_x;
+        cfg.size_y = current.size_y;
+        cfg.n_agents = current.n_agents;
+        cfg.shader_seed = current.shader_seed;
+        cfg.seed = current.seed;
+        cfg.r_init = current.r_init;
+        cfg.n_fix = current.n_fix;
+        cfg.r_fix_min = current.r_fix_min;
+        cfg.r_fix_max = current.r_fix_max;
+        cfg.nutriment_image = current.nutriment_image.clone();
+        cfg.obstacle_image = current.obstacle_image.clone();
+        if species_params(&cfg).len() != self.species.len() {
+            cfg.species = current.species.clone();
+        }
+        self.cfg = cfg;
+
+        self.update_parameters();
+        self.write_parameters();
+        Ok(())
+    }
+
+    // Parameters of the next step from the configuration and timeline
+    fn update_parameters(&mut self) {
+        let cfg = at_step(&self.cfg, self.steps);
+        self.species = species_params(&cfg);
+        self.uniforms = uniforms(&cfg, self.species.len(),
+                                 self.uniforms.n_channels as usize);
+    }
+
+    // Upload the parameters of the next step, applied with the next
+    // submission
+    fn write_parameters(&self) {
+        self.queue.write_buffer(&self.uniform_buffer, 0,
+                                bytemuck::cast_slice(&[self.uniforms]));
+        self.queue.write_buffer(&self.species_buffer, 0,
+                                bytemuck::cast_slice(&self.species));
+    }
+
+    // Record drawing the slime field and the agents into the offscreen
+    // scene and the post processing of the scene into view
+    pub fn encode_render(&self, encoder: &mut wgpu::CommandEncoder,
+                  view: &wgpu::TextureView) {
+        // Render pass
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Render Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &self.post.scene,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color {
+                            r: 0.0,
+                            g: 0.0,
+                            b: 0.0,
+                            a: 1.0,
+                        }),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_bind_group(0, &self.bind_groups_r[self.parity],
+                                       &[]);
+            render_pass.set_pipeline(&self.render_pipeline);
+            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(self.index_buffer.slice(..),
+                                         wgpu::IndexFormat::Uint16);
+            render_pass.draw_indexed(0..6, 0, 0..1);
+        }
+        if self.cfg.layers.agents.visible {
+            self.sprites.encode(&self.queue, encoder, &self.post.scene,
+                                &self.agents, self.cfg.n_agents.unwrap(),
+                                SpriteParams::new(&self.cfg));
+        }
+        self.post.encode(&self.queue, encoder, view,
+                         PostParams::new(&self.cfg, self.steps));
+    }
+
+    fn create_encoder(&self) -> wgpu::CommandEncoder {
+        let ce_desc = wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder")
+        };
+        self.device.create_command_encoder(&ce_desc)
+    }
+
+    // Copy a storage buffer to a mappable one and wait for its content
+    fn read_buffer<T: bytemuck::Pod>(&self, buffer: &wgpu::Buffer,
+                                     size: wgpu::BufferAddress) -> Vec<T> {
+        let staging = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ |
+                   wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = self.create_encoder();
+        encoder.copy_buffer_to_buffer(buffer, 0, &staging, 0, size);
+        self.queue.submit(iter::once(encoder.finish()));
+
+        let slice = staging.slice(..);
+        let (tx, rx) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |res| {
+            tx.send(res).unwrap();
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        rx.recv().unwrap().expect("Mapping readback buffer failed");
+
+        let data = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+        staging.unmap();
+        data
+    }
+}
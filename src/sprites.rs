@@ -0,0 +1,171 @@
+// Render layer drawing every agent as a small sprite, coloured by the
+// agent colour or its heading and blended with the layers below.
+use bytemuck::{Pod, Zeroable};
+
+use crate::config::{BlendMode, Boundary, Config};
+use crate::gpu_create::Agent;
+use crate::post::SCENE_FORMAT;
+
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct SpriteParams {
+    pub size_x: f32,
+    pub size_y: f32,
+    pub diameter: f32,
+    pub opacity: f32,
+    pub coloring: u32,  // 0: agent colour, 1: heading
+    pub blend: u32,  // 0: add, 1: screen, 2: multiply
+    pub wrap: u32,  // 1: sprites crossing an edge continue at the opposite
+    _padding: u32
+}
+
+unsafe impl Zeroable for SpriteParams {}
+unsafe impl Pod for SpriteParams {}
+
+impl SpriteParams {
+    pub fn new(cfg: &Config) -> Self {
+        SpriteParams {
+            size_x: cfg.size_x.unwrap() as f32,
+            size_y: cfg.size_y.unwrap() as f32,
+            diameter: cfg.layers.agent_size,
+            opacity: cfg.layers.agents.opacity,
+            coloring: cfg.layers.agent_coloring as u32,
+            blend: cfg.layers.agents.blend as u32,
+            wrap: (cfg.boundary == Boundary::Wrap) as u32,
+            _padding: 0
+        }
+    }
+}
+
+pub struct AgentSprites {
+    // one for each blend mode
+    pipelines: [wgpu::RenderPipeline; 3],
+    params_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup
+}
+
+impl AgentSprites {
+    // Drawn into the scene of the post processing
+    pub fn new(device: &wgpu::Device) -> Self {
+        let shader = device.create_shader_module(
+            wgpu::include_wgsl!("../Shader/sprites.wgsl"));
+
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sprite Params"),
+            size: std::mem::size_of::<SpriteParams>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false
+        });
+        let layout = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None },
+                        count: None
+                    }
+                ],
+                label: Some("Sprite Layout")
+            });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &layout,
+            label: Some("Sprite BG"),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding()
+                }
+            ]
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Sprites"),
+                bind_group_layouts: &[&layout],
+                push_constant_ranges: &[]
+            });
+        // The shader returns the weighted sprite colour, for multiply
+        // the factor of the colour below
+        let blend_component = |mode| {
+            let (src_factor, dst_factor) = match mode {
+                BlendMode::Add => (wgpu::BlendFactor::One,
+                                   wgpu::BlendFactor::One),
+                BlendMode::Screen => (wgpu::BlendFactor::OneMinusDst,
+                                      wgpu::BlendFactor::One),
+                BlendMode::Multiply => (wgpu::BlendFactor::Dst,
+                                        wgpu::BlendFactor::Zero)
+            };
+            wgpu::BlendComponent {
+                src_factor,
+                dst_factor,
+                operation: wgpu::BlendOperation::Add
+            }
+        };
+        let create_pipeline = |mode| device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("Sprite Pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[Agent::desc()]
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: SCENE_FORMAT,
+                        blend: Some(wgpu::BlendState {
+                            color: blend_component(mode),
+                            alpha: blend_component(mode)
+                        }),
+                        write_mask: wgpu::ColorWrites::ALL
+                    })]
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None
+            });
+
+        Self {
+            pipelines: [BlendMode::Add, BlendMode::Screen, BlendMode::Multiply]
+                .map(create_pipeline),
+            params_buffer,
+            bind_group
+        }
+    }
+
+    // Record blending n_agents sprites of agents into the scene
+    pub fn encode(&self, queue: &wgpu::Queue,
+                  encoder: &mut wgpu::CommandEncoder,
+                  scene: &wgpu::TextureView, agents: &wgpu::Buffer,
+                  n_agents: u32, params: SpriteParams) {
+        queue.write_buffer(&self.params_buffer, 0,
+                           bytemuck::cast_slice(&[params]));
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Sprite Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: scene,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true
+                }
+            })],
+            depth_stencil_attachment: None
+        });
+        pass.set_pipeline(&self.pipelines[params.blend as usize]);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.set_vertex_buffer(0, agents.slice(..));
+        // the wrapped copies of each quad
+        let quads = if params.wrap == 1 { 4 } else { 1 };
+        pass.draw(0 .. 6 * quads, 0 .. n_agents);
+    }
+}
@@ -0,0 +1,66 @@
+// Seam check of tileable textures: compare the difference of neighbouring
+// pixels across the left/right and top/bottom edges with the differences
+// of the neighbouring lines next to the edges.
+
+
+// Seams may differ at most this much more than the lines next to them
+pub const MAX_SEAM_RATIO: f32 = 2.;
+// Lines on each side of a seam used as reference
+const NEIGHBOURS: usize = 2;
+
+// Difference across a seam relative to the lines next to it,
+// close to 1 for a seamless texture
+#[derive(Clone, Copy, Debug)]
+pub struct Seams {
+    pub left_right: f32,
+    pub top_bottom: f32
+}
+
+impl Seams {
+    // pixels: rows of width * channels values
+    pub fn measure(pixels: &[u8], width: usize, height: usize,
+                   channels: usize) -> Seams {
+        let at = |x: usize, y: usize, c: usize|
+            pixels[(x + y*width)*channels + c] as f32;
+        // Difference between line i and i + 1, the last one is the seam
+        let mut columns = vec![0.; width];
+        let mut rows = vec![0.; height];
+
+        for (y, row) in rows.iter_mut().enumerate() {
+            for (x, column) in columns.iter_mut().enumerate() {
+                for c in 0 .. channels {
+                    let v = at(x, y, c);
+                    *column += (at((x + 1) % width, y, c) - v).abs();
+                    *row += (at(x, (y + 1) % height, c) - v).abs();
+                }
+            }
+        }
+
+        Seams {
+            left_right: ratio(&columns),
+            top_bottom: ratio(&rows)
+        }
+    }
+
+    pub fn is_seamless(&self) -> bool {
+        self.left_right <= MAX_SEAM_RATIO && self.top_bottom <= MAX_SEAM_RATIO
+    }
+}
+
+fn ratio(diffs: &[f32]) -> f32 {
+    let n = diffs.len();
+    let k = NEIGHBOURS.min(n.saturating_sub(1) / 2);
+    if k == 0 {
+        return 1.;  // no inner lines to compare with
+    }
+    let seam = diffs[n - 1];
+    let inner = (diffs[.. k].iter().sum::<f32>() +
+                 diffs[n - 1 - k .. n - 1].iter().sum::<f32>()) / (2*k) as f32;
+    if inner > 0. {
+        seam / inner
+    } else if seam > 0. {
+        f32::INFINITY
+    } else {
+        1.
+    }
+}
@@ -0,0 +1,111 @@
+// Keyframed parameters, interpolated between the keyframes for every step.
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+
+// How the values move towards a keyframe
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Interpolation {
+    // Constant rate of change
+    #[default]
+    Linear,
+    // Smoothstep, slow at both keyframes
+    Smooth
+}
+
+// Parameter values reached at a step, unset values are not animated
+// by this keyframe
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Keyframe {
+    pub step: u32,
+    pub interpolation: Interpolation,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deposit: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decay: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub v: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub phi_sens: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub turn_speed: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sens_range_min: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sens_range_max: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub w_nutriment: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repellent: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repellent_decay: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color_blend: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub consumption: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub regrowth: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nutriment_diffusion: Option<f32>
+}
+
+type Parameter = (fn(&Keyframe) -> Option<f32>, fn(&mut Config) -> &mut f32);
+
+// Value in a keyframe and field in the configuration of every
+// animated parameter
+const PARAMETERS: [Parameter; 14] = [
+    (|k| k.deposit, |c| &mut c.deposit),
+    (|k| k.decay, |c| &mut c.decay),
+    (|k| k.v, |c| &mut c.v),
+    (|k| k.phi_sens, |c| &mut c.phi_sens),
+    (|k| k.turn_speed, |c| &mut c.turn_speed),
+    (|k| k.sens_range_min, |c| &mut c.sens_range_min),
+    (|k| k.sens_range_max, |c| &mut c.sens_range_max),
+    (|k| k.w_nutriment, |c| &mut c.w_nutriment),
+    (|k| k.repellent, |c| &mut c.repellent),
+    (|k| k.repellent_decay, |c| &mut c.repellent_decay),
+    (|k| k.color_blend, |c| &mut c.color_blend),
+    (|k| k.consumption, |c| &mut c.consumption),
+    (|k| k.regrowth, |c| &mut c.regrowth),
+    (|k| k.nutriment_diffusion, |c| &mut c.nutriment_diffusion)
+];
+
+impl Interpolation {
+    // Share of the way to the keyframe for t in 0..1
+    pub fn ease(self, t: f32) -> f32 {
+        match self {
+            Interpolation::Linear => t,
+            Interpolation::Smooth => t * t * (3. - 2. * t)
+        }
+    }
+}
+
+// Configuration with the animated values of the step.
+// The configured values hold at step 0, each parameter moves from
+// keyframe to keyframe and keeps the value of its last keyframe.
+// Keyframes have to be sorted by step.
+pub fn at_step(cfg: &Config, step: u32) -> Config {
+    let mut animated = cfg.clone();
+    for (key, field) in PARAMETERS {
+        let (mut from_step, mut current) = (0, *field(&mut animated));
+        for keyframe in &cfg.timeline {
+            let value = match key(keyframe) {
+                Some(value) => value,
+                None => continue
+            };
+            if keyframe.step <= step {
+                (from_step, current) = (keyframe.step, value);
+            } else {
+                let t = (step - from_step) as f32 /
+                        (keyframe.step - from_step) as f32;
+                current += (value - current) * keyframe.interpolation.ease(t);
+                break;
+            }
+        }
+        *field(&mut animated) = current;
+    }
+    animated
+}
@@ -0,0 +1,109 @@
+// Lookup tables of the colour maps and tone mapping settings
+use physarum_art::colormap::{Colormap, LUT_SIZE};
+use physarum_art::config::ToneMapping;
+use physarum_art::{Config, ConfigError};
+
+
+fn rgb(lut: &[physarum_art::gpu_create::Color], i: usize) -> [f32; 3] {
+    [lut[i].r, lut[i].g, lut[i].b]
+}
+
+#[test]
+fn builtin_maps_go_from_dark_to_bright() {
+    for colormap in [Colormap::Magma, Colormap::Viridis, Colormap::Inferno] {
+        let lut = colormap.lut().unwrap();
+        assert_eq!(lut.len(), LUT_SIZE);
+        let sum = |i| rgb(&lut, i).iter().sum::<f32>();
+        assert!(sum(0) < 0.2, "{:?} starts at {:?}", colormap, rgb(&lut, 0));
+        assert!(sum(LUT_SIZE - 1) > 1.5, "{:?} ends at {:?}",
+                colormap, rgb(&lut, LUT_SIZE - 1));
+        assert!(lut.iter().all(|c| (0. ..= 1.).contains(&c.r) &&
+                                   (0. ..= 1.).contains(&c.g) &&
+                                   (0. ..= 1.).contains(&c.b)));
+    }
+    // viridis ends in yellow, magma in a light pink
+    let viridis = Colormap::Viridis.lut().unwrap();
+    let [r, g, b] = rgb(&viridis, LUT_SIZE - 1);
+    assert!(r > 0.9 && g > 0.7 && b < 0.05);
+    let magma = Colormap::Magma.lut().unwrap();
+    let [r, g, b] = rgb(&magma, LUT_SIZE - 1);
+    assert!(r > 0.9 && g > 0.9 && b > 0.4);
+}
+
+#[test]
+fn gradient_interpolates_between_stops() {
+    let colormap = Colormap::Gradient(vec![[0., 0., 0.], [1., 0., 0.],
+                                           [1., 1., 1.]]);
+    let lut = colormap.lut().unwrap();
+    assert_eq!(rgb(&lut, 0), [0., 0., 0.]);
+    assert_eq!(rgb(&lut, LUT_SIZE - 1), [1., 1., 1.]);
+    let [r, g, b] = rgb(&lut, LUT_SIZE / 4);
+    assert!((r - 0.5).abs() < 0.01 && g == 0. && b == 0.);
+    let [r, g, b] = rgb(&lut, 3 * LUT_SIZE / 4);
+    assert!(r == 1. && (g - 0.5).abs() < 0.01 && (b - 0.5).abs() < 0.01);
+}
+
+#[test]
+fn lut_from_image_strip() {
+    let dir = std::env::temp_dir().join("physarum_colormap");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("lut.png");
+    image::RgbaImage::from_fn(64, 4, |x, _| {
+        let v = (x * 4) as u8;
+        image::Rgba([v, 0, 255 - v, 255])
+    }).save(&path).unwrap();
+
+    let lut = Colormap::Image(path.to_string_lossy().into()).lut().unwrap();
+    assert_eq!(lut.len(), LUT_SIZE);
+    assert!(lut[0].r < 0.01 && lut[0].b > 0.9);
+    assert!(lut[LUT_SIZE - 1].r > 0.9 && lut[LUT_SIZE - 1].b < 0.01);
+    assert!(lut.windows(2).all(|w| w[1].r >= w[0].r));
+
+    match Colormap::Image("does/not/exist.png".into()).lut() {
+        Err(ConfigError::Image(path, _)) => assert!(path.contains("exist")),
+        _ => panic!("expected an image error")
+    }
+}
+
+#[test]
+fn colormap_from_json() {
+    let cfg: Config = serde_json::from_str(r#"{"colormap": "inferno"}"#)
+        .unwrap();
+    assert_eq!(cfg.colormap, Colormap::Inferno);
+    let cfg: Config = serde_json::from_str(
+        r#"{"colormap": {"gradient": [[0, 0, 0], [0, 0.5, 1]]}}"#).unwrap();
+    assert_eq!(cfg.colormap,
+               Colormap::Gradient(vec![[0., 0., 0.], [0., 0.5, 1.]]));
+    assert_eq!(Config::default().colormap, Colormap::Species);
+}
+
+#[test]
+fn gradient_is_validated() {
+    let cfg = Config {
+        colormap: Colormap::Gradient(vec![[1., -1., 0.]]),
+        ..Config::default()
+    };
+    match cfg.validate() {
+        Err(ConfigError::Invalid(errors)) => {
+            assert_eq!(errors.len(), 2);
+            assert!(errors.iter().all(|e| e.starts_with("colormap:")));
+        }
+        _ => panic!("expected an invalid gradient")
+    }
+}
+
+#[test]
+fn tone_mapping_from_json() {
+    let cfg: Config = serde_json::from_str(
+        r#"{"tone_mapping": "aces", "exposure": 2.5, "gamma": 2.2}"#).unwrap();
+    assert_eq!(cfg.tone_mapping, ToneMapping::Aces);
+    assert_eq!((cfg.exposure, cfg.gamma), (2.5, 2.2));
+    assert_eq!(Config::default().tone_mapping, ToneMapping::Clamp);
+
+    let cfg = Config { exposure: 0., gamma: -1., ..Config::default() };
+    match cfg.validate() {
+        Err(ConfigError::Invalid(errors)) => assert_eq!(errors, [
+            "exposure: 0 must be > 0", "gamma: -1 must be > 0"]),
+        _ => panic!("expected invalid display values")
+    }
+}
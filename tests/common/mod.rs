@@ -0,0 +1,31 @@
+// Helpers shared by the GPU tests
+use std::sync::Arc;
+
+use physarum_art::gpu_create::device_limits;
+
+
+// Device with the limits of the simulation, None without an adapter
+pub fn device() -> Option<(Arc<wgpu::Device>, Arc<wgpu::Queue>)> {
+    let instance = wgpu::Instance::new(wgpu::Backends::all());
+    let adapter = pollster::block_on(instance.request_adapter(
+        &wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            compatible_surface: None,
+            force_fallback_adapter: false,
+        }));
+    let adapter = match adapter {
+        Some(adapter) => adapter,
+        None => {
+            eprintln!("No graphics adapter found, skipping GPU test");
+            return None;
+        }
+    };
+    let (device, queue) = pollster::block_on(adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            label: None,
+            features: wgpu::Features::empty(),
+            limits: device_limits()
+        },
+        None)).unwrap();
+    Some((Arc::new(device), Arc::new(queue)))
+}
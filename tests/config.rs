@@ -0,0 +1,205 @@
+// Partial config files over random values and validation of every field
+use physarum_art::colormap::Colormap;
+use physarum_art::config::{seeded_rng, Boundary, DepositMode, STREAM_CONFIG};
+use physarum_art::{Config, ConfigError};
+
+
+// Config file with the content json, one per test
+fn config_file(name: &str, json: &str) -> String {
+    let dir = std::env::temp_dir().join("physarum_config");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    std::fs::write(&path, json).unwrap();
+    path.to_string_lossy().into()
+}
+
+fn random() -> Config {
+    Config::new(&mut seeded_rng(5, STREAM_CONFIG))
+}
+
+fn invalid(result: Result<(), ConfigError>) -> Vec<String> {
+    match result {
+        Err(ConfigError::Invalid(errors)) => errors,
+        other => panic!("expected invalid values, got {:?}", other)
+    }
+}
+
+#[test]
+fn partial_file_keeps_other_values() {
+    let path = config_file("partial.json",
+                           r#"{"decay": 0.5, "boundary": "wrap"}"#);
+    let mut cfg = random();
+    cfg.load_json(&path).unwrap();
+
+    assert_eq!(cfg.decay, 0.5);
+    assert_eq!(cfg.boundary, Boundary::Wrap);
+    let expected = Config { decay: 0.5, boundary: cfg.boundary,
+                            ..random() };
+    assert_eq!(cfg, expected);
+}
+
+#[test]
+fn empty_file_keeps_every_value() {
+    let path = config_file("empty.json", "{}");
+    let mut cfg = random();
+    cfg.load_json(&path).unwrap();
+    assert_eq!(cfg, random());
+
+    let mut cfg = Config::default();
+    cfg.load_json(&path).unwrap();
+    assert_eq!(cfg, Config::default());
+}
+
+#[test]
+fn unknown_key_is_rejected() {
+    let path = config_file("unknown.json", r#"{"decay": 0.5, "dekay": 0.5}"#);
+    let mut cfg = random();
+    match cfg.load_json(&path) {
+        Err(ConfigError::Parse(e)) => {
+            assert!(e.to_string().contains("dekay"), "{}", e);
+        }
+        other => panic!("expected a parse error, got {:?}", other)
+    }
+    // nothing is taken over
+    assert_eq!(cfg, random());
+
+    let path = config_file("unknown_nested.json",
+                           r#"{"layers": {"trails": {"opcity": 0.5}}}"#);
+    match cfg.load_json(&path) {
+        Err(ConfigError::Parse(e)) => {
+            assert!(e.to_string().contains("opcity"), "{}", e);
+        }
+        other => panic!("expected a parse error, got {:?}", other)
+    }
+    assert_eq!(cfg, random());
+}
+
+#[test]
+fn every_invalid_field_is_reported() {
+    let path = config_file("invalid.json", r#"{
+        "decay": 1.5,
+        "sens_range_min": 8,
+        "sens_range_max": 4,
+        "r_fix_min": 6,
+        "r_fix_max": 6
+    }"#);
+    let mut cfg = random();
+    let errors = invalid(cfg.load_json(&path));
+    assert_eq!(cfg, random());
+
+    assert_eq!(errors.len(), 3, "{:?}", errors);
+    for field in ["decay", "sens_range_min", "r_fix_min"] {
+        assert!(errors.iter().any(|e| e.starts_with(field)),
+                "{} missing in {:?}", field, errors);
+    }
+    let message = ConfigError::Invalid(errors).to_string();
+    assert!(message.contains("decay: 1.5 must be within 0..1"), "{}", message);
+    assert!(message.contains("sens_range_max (4)"), "{}", message);
+    assert!(message.contains("r_fix_max (6)"), "{}", message);
+}
+
+#[test]
+fn nested_partial_file_keeps_other_values() {
+    let path = config_file("nested.json",
+                           r#"{"layers": {"trails": {"opacity": 0.5}}}"#);
+    let mut cfg = random();
+    cfg.layers.nutriment.visible = true;
+    cfg.layers.agent_size = 3.;
+    let before = cfg.clone();
+    cfg.load_json(&path).unwrap();
+
+    let mut expected = before;
+    expected.layers.trails.opacity = 0.5;
+    assert_eq!(cfg, expected);
+
+    // empty objects and ones without a known key are merged as well
+    for json in [r#"{"layers": {}}"#, r#"{"layers": {"trails": {}}}"#] {
+        let path = config_file("nested_empty.json", json);
+        cfg.load_json(&path).unwrap();
+        assert_eq!(cfg, expected, "{}", json);
+    }
+}
+
+// A saved run loads back into the same configuration
+#[test]
+fn saved_file_loads_the_same_values() {
+    let mut saved = Config {
+        boundary: Boundary::Respawn,
+        colormap: Colormap::Gradient(vec![[0.; 3], [0.5, 0.2, 1.]]),
+        species: vec![Default::default(); 2],
+        size_x: Some(300),
+        size_y: Some(200),
+        n_agents: Some(5000),
+        shader_seed: Some(9),
+        seed: Some(u64::MAX),
+        ..random()
+    };
+    saved.species[1].deposit = Some(0.2);
+    saved.layers.agents.visible = true;
+    let path = config_file("saved.json", "");
+    saved.save_json(&path).unwrap();
+
+    let mut loaded = Config::default();
+    loaded.load_json(&path).unwrap();
+    assert_eq!(loaded, saved);
+}
+
+// An enum variant isn't merged into another one
+#[test]
+fn colormap_variant_is_replaced() {
+    let path = config_file("variant.json",
+                           r#"{"colormap": {"image": "lut.png"}}"#);
+    let mut cfg = Config {
+        colormap: Colormap::Gradient(vec![[0.; 3], [1.; 3]]),
+        ..random()
+    };
+    cfg.load_json(&path).unwrap();
+    assert_eq!(cfg.colormap, Colormap::Image("lut.png".to_string()));
+}
+
+// Re-rolling keeps everything that isn't drawn at random
+#[test]
+fn randomise_keeps_the_session_settings() {
+    let mut cfg = Config {
+        boundary: Boundary::Wrap,
+        nutriment_image: Some("nutriment.png".to_string()),
+        colormap: Colormap::Magma,
+        bloom: 1.,
+        species: vec![Default::default(); 2],
+        size_x: Some(300),
+        seed: Some(5),
+        ..random()
+    };
+    cfg.layers.nutriment.visible = true;
+    let before = cfg.clone();
+    cfg.randomise(&mut seeded_rng(6, STREAM_CONFIG));
+
+    let rolled = Config::new(&mut seeded_rng(6, STREAM_CONFIG));
+    assert_eq!(cfg.decay, rolled.decay);
+    assert_eq!(cfg.sens_range_max, rolled.sens_range_max);
+    assert_ne!(cfg.decay, before.decay);
+    let expected = Config { r_init: cfg.r_init, deposit: cfg.deposit,
+                            decay: cfg.decay, v: cfg.v,
+                            phi_sens: cfg.phi_sens,
+                            turn_speed: cfg.turn_speed,
+                            sens_range_min: cfg.sens_range_min,
+                            sens_range_max: cfg.sens_range_max,
+                            n_fix: cfg.n_fix, r_fix_min: cfg.r_fix_min,
+                            r_fix_max: cfg.r_fix_max,
+                            w_nutriment: cfg.w_nutriment,
+                            ..before };
+    assert_eq!(cfg, expected);
+}
+
+// All agents depositing into one cell have to fit the atomic fixed point
+#[test]
+fn atomic_deposit_overflow_is_rejected() {
+    let cfg = Config { deposit: 0.1, deposit_mode: DepositMode::Atomic,
+                       n_agents: Some(2_u32.pow(22)), ..random() };
+    cfg.validate().unwrap();
+
+    let cfg = Config { deposit: 2000., ..cfg };
+    let errors = invalid(cfg.validate());
+    assert!(errors.iter().any(|e| e.starts_with("deposit: 2000 by 4194304")),
+            "{:?}", errors);
+}
@@ -0,0 +1,645 @@
+// Compare the shaders with the CPU reference on small grids.
+// The GPU tests are skipped if no adapter (e.g. lavapipe, llvmpipe) is found.
+use physarum_art::cpu::{diffuse, CpuSimulation};
+use physarum_art::gpu_create::{Agent, ABSORBED};
+use physarum_art::config::{Boundary, ColorSampling, DepositMode, Species};
+use physarum_art::timeline::Keyframe;
+use physarum_art::{Config, ConfigError, Simulation};
+
+mod common;
+use common::device;
+
+
+fn small_config() -> Config {
+    Config {
+        r_init: 20.,
+        deposit: 0.05,
+        decay: 0.9,
+        v: 1.5,
+        sens_range_min: 1.,
+        sens_range_max: 6.,
+        n_fix: 4,
+        r_fix_min: 2,
+        r_fix_max: 6,
+        w_nutriment: 2.,
+        // not multiples of the workgroup size
+        size_x: Some(61),
+        size_y: Some(47),
+        n_agents: Some(1000),
+        seed: Some(42),
+        ..Config::default()
+    }
+}
+
+fn cpu_state(sim: &Simulation) -> CpuSimulation {
+    CpuSimulation {
+        uniforms: sim.uniforms(),
+        species: sim.species().to_vec(),
+        agents: sim.read_agents(),
+        slime: sim.read_slime(),
+        nutriment: sim.read_nutriment(),
+        capacity: sim.read_nutriment_capacity(),
+        obstacles: sim.read_obstacles()
+    }
+}
+
+fn agent_differs(a: &Agent, b: &Agent) -> bool {
+    (a.x - b.x).abs() > 1e-3 || (a.y - b.y).abs() > 1e-3 ||
+        (a.phi - b.phi).abs() > 1e-3
+}
+
+// Floating point differences can flip a sensor cell now and then
+const MAX_MISMATCH: f32 = 0.01;
+
+// Cells influenced by more than one agent deposit in the last step.
+// Concurrent deposits into the same cell can get lost on the GPU.
+fn crowded_cells(agents: &[Agent], size_x: u32, size_y: u32) -> Vec<bool> {
+    let mut count = vec![0; (size_x * size_y) as usize];
+    for a in agents {
+        if a.x == ABSORBED {
+            continue;
+        }
+        count[a.x as usize + a.y as usize * size_x as usize] += 1;
+    }
+    let mut crowded = vec![false; count.len()];
+    for y in 0 .. size_y as i32 {
+        for x in 0 .. size_x as i32 {
+            if count[(x + y*size_x as i32) as usize] < 2 {
+                continue;
+            }
+            for dx in -1 .. 2 {
+                for dy in -1 .. 2 {
+                    let (nx, ny) = (x + dx, y + dy);
+                    if nx >= 0 && nx < size_x as i32 &&
+                       ny >= 0 && ny < size_y as i32 {
+                        crowded[(nx + ny*size_x as i32) as usize] = true;
+                    }
+            }}
+        }
+    }
+    crowded
+}
+
+// 8x8 grid without agents
+fn empty_grid_uniforms() -> physarum_art::gpu_create::Uniforms {
+    let cfg = Config { decay: 0.9, ..small_config() };
+    physarum_art::gpu_create::Uniforms {
+        n_agents: 0, size_x: 8, size_y: 8, n_species: 1, n_channels: 1,
+        decay: cfg.decay, repellent_decay: cfg.repellent_decay,
+        sens_range_min: cfg.sens_range_min,
+        sens_range_max: cfg.sens_range_max,
+        sense_steps: cfg.sens_range_max - cfg.sens_range_min + 1.,
+        seed: 0,
+        deposit_mode: 0,
+        boundary: 0,
+        color_sampling: 0,
+        color_blend: cfg.color_blend,
+        consumption: 0.,
+        regrowth: 0.,
+        nutriment_diffusion: 0.,
+        colormap: 0, tone_mapping: 0, exposure: 1., gamma: 1., white: 1.,
+        nutriment_opacity: 0., nutriment_blend: 0, trails_opacity: 1.,
+        trails_blend: 0, obstacles_opacity: 0., obstacles_blend: 0,
+        deposit_scale: 1048576.
+    }
+}
+
+#[test]
+fn diffusion_spreads_peak() {
+    let mut cpu = CpuSimulation {
+        uniforms: empty_grid_uniforms(),
+        species: Vec::new(),
+        agents: Vec::new(),
+        slime: vec![0.; 64],
+        nutriment: vec![0.; 64],
+        capacity: vec![0.; 64],
+        obstacles: vec![0; 64]
+    };
+    cpu.slime[3 + 4*8] = 0.9;
+    cpu.step();
+
+    let expected = 0.9 / 9. * 0.9;
+    for y in 0 .. 8 {
+        for x in 0 .. 8 {
+            let near = (2 ..= 4).contains(&x) && (3 ..= 5).contains(&y);
+            let value = cpu.slime[x + y*8];
+            if near {
+                assert!((value - expected).abs() < 1e-6, "{} {}", x, y);
+            } else {
+                assert_eq!(value, 0.);
+            }
+        }
+    }
+    // Corner cells only have 4 neighbours inside the grid
+    let corner = diffuse(&cpu.uniforms, &[0.9; 64], &[0; 64])[0];
+    assert!((corner - 4. * 0.9 / 9. * 0.9).abs() < 1e-6);
+}
+
+// Nothing leaves a torus, the total only decays
+#[test]
+fn wrapped_diffusion_keeps_mass() {
+    let mut u = empty_grid_uniforms();
+    u.boundary = Boundary::Wrap as u32;
+    let slime: Vec<f32> = (0 .. 64).map(|i| (i % 7) as f32 * 0.1).collect();
+    let total: f32 = slime.iter().sum();
+    let diffused: f32 = diffuse(&u, &slime, &[0; 64]).iter().sum();
+    assert!((diffused - total * u.decay).abs() < 1e-4);
+
+    // Mirrored edges keep the mass as well for a uniform field
+    u.boundary = Boundary::Reflect as u32;
+    let mirrored = diffuse(&u, &[0.5; 64], &[0; 64]);
+    assert!(mirrored.iter().all(|v| (v - 0.5*u.decay).abs() < 1e-6));
+}
+
+// Compare every single step starting from the current GPU state
+fn compare_steps(cfg: &Config, steps: u32) {
+    let (device, queue) = match device() {
+        Some(dq) => dq,
+        None => return
+    };
+    let mut sim = Simulation::new(cfg, device, queue,
+                                  wgpu::TextureFormat::Rgba8UnormSrgb)
+        .unwrap();
+    let (size_x, size_y) = sim.size();
+    let atomic = cfg.deposit_mode == DepositMode::Atomic;
+
+    for step in 0 .. steps {
+        let mut cpu = cpu_state(&sim);
+        cpu.step();
+        sim.step(1);
+
+        let agents = sim.read_agents();
+        let differing = agents.iter().zip(&cpu.agents)
+            .filter(|(a, b)| agent_differs(a, b))
+            .count();
+        assert!((differing as f32) <= MAX_MISMATCH * agents.len() as f32,
+                "step {}: {} of {} agents differ",
+                step, differing, agents.len());
+
+        let slime = sim.read_slime();
+        let crowded = crowded_cells(&cpu.agents, size_x, size_y);
+        let mut differing = 0;
+        // all trail channels
+        for (i, (gpu, cpu)) in slime.iter().zip(&cpu.slime).enumerate() {
+            if crowded[i % crowded.len()] && !atomic {
+                continue;
+            }
+            let tolerance = 1e-5 + 1e-4 * cpu.abs();
+            if (gpu - cpu).abs() > tolerance {
+                differing += 1;
+            }
+        }
+        let cells = slime.len();
+        assert!((differing as f32) <= MAX_MISMATCH * cells as f32,
+                "step {}: {} of {} cells differ", step, differing, cells);
+
+        let nutriment = sim.read_nutriment();
+        let differing = nutriment.iter().zip(&cpu.nutriment).enumerate()
+            .filter(|(i, (gpu, cpu))| (atomic || !crowded[*i]) &&
+                    (*gpu - *cpu).abs() > 1e-5 + 1e-4 * cpu.abs())
+            .count();
+        let cells = nutriment.len();
+        assert!((differing as f32) <= MAX_MISMATCH * cells as f32,
+                "step {}: {} of {} nutriment cells differ",
+                step, differing, cells);
+    }
+}
+
+#[test]
+fn gpu_step_matches_cpu() {
+    compare_steps(&small_config(), 10);
+}
+
+// Crowded cells must not lose deposits
+#[test]
+fn atomic_deposit_matches_cpu() {
+    let cfg = Config {
+        deposit_mode: DepositMode::Atomic,
+        n_agents: Some(5000),
+        ..small_config()
+    };
+    compare_steps(&cfg, 10);
+}
+
+// Dense trails are kept above 1 for tone mapping
+#[test]
+fn trails_are_unlimited() {
+    let cfg = Config {
+        deposit: 2.,
+        deposit_mode: DepositMode::Atomic,
+        n_agents: Some(5000),
+        ..small_config()
+    };
+    compare_steps(&cfg, 5);
+
+    if let Some((device, queue)) = device() {
+        let mut sim = Simulation::new(&cfg, device, queue,
+                                      wgpu::TextureFormat::Rgba8UnormSrgb)
+            .unwrap();
+        sim.step(5);
+        let max = sim.read_slime().into_iter().fold(0., f32::max);
+        assert!(max > 1., "densest trail {}", max);
+    }
+}
+
+// Agents reach the edges within a few steps
+#[test]
+fn boundaries_match_cpu() {
+    for boundary in [Boundary::Wrap, Boundary::Reflect,
+                     Boundary::Respawn, Boundary::Absorb] {
+        let cfg = Config {
+            boundary,
+            deposit_mode: DepositMode::Atomic,
+            ..small_config()
+        };
+        compare_steps(&cfg, 20);
+    }
+}
+
+// Competing colonies with own parameters and trails
+#[test]
+fn species_match_cpu() {
+    let species = Species {
+        v: Some(2.),
+        turn_speed: Some(0.6),
+        deposit: Some(0.1),
+        color: [1., 0., 0.],
+        interaction: vec![0.5, -1., 2.],
+        ..Species::default()
+    };
+    let cfg = Config {
+        species: vec![Species::default(), species, Species::default()],
+        deposit_mode: DepositMode::Atomic,
+        n_agents: Some(3000),
+        ..small_config()
+    };
+    compare_steps(&cfg, 10);
+}
+
+// Repellent trails with their own decay and repellent nutriment zones
+#[test]
+fn repellent_matches_cpu() {
+    let species = Species {
+        repellent: Some(0.2),
+        w_nutriment: Some(-3.),
+        repulsion: vec![-2., 0.5],
+        ..Species::default()
+    };
+    let cfg = Config {
+        species: vec![Species::default(), species],
+        repellent: 0.05,
+        repellent_decay: 0.97,
+        deposit_mode: DepositMode::Atomic,
+        n_agents: Some(3000),
+        ..small_config()
+    };
+    compare_steps(&cfg, 10);
+}
+
+// Thousands of agents per cell keep their atomic deposits, the fixed
+// point scale leaves room for all of them
+#[test]
+fn crowded_atomic_deposits_match_cpu() {
+    let cfg = Config {
+        r_init: 2.,
+        deposit: 1.,
+        n_agents: Some(100_000),
+        deposit_mode: DepositMode::Atomic,
+        ..small_config()
+    };
+    let (device, queue) = match device() {
+        Some(dq) => dq,
+        None => return
+    };
+    let mut sim = Simulation::new(&cfg, device, queue,
+                                  wgpu::TextureFormat::Rgba8UnormSrgb)
+        .unwrap();
+    let mut cpu = cpu_state(&sim);
+    sim.step(1);
+    cpu.step();
+
+    let slime = sim.read_slime();
+    let max = slime.iter().cloned().fold(0., f32::max);
+    // more than the 4096 a cell held at the finest scale
+    assert!(max > 4096. * cfg.decay, "{}", max);
+    for (gpu, cpu) in slime.iter().zip(&cpu.slime) {
+        assert!((gpu - cpu).abs() <= 1e-3 * cpu.max(1.), "{} {}", gpu, cpu);
+    }
+}
+
+// Agents eat the nutriment zones, which spread and grow back
+#[test]
+fn consumption_matches_cpu() {
+    let cfg = Config {
+        consumption: 0.05,
+        regrowth: 0.05,
+        nutriment_diffusion: 0.2,
+        n_fix: 8,
+        deposit_mode: DepositMode::Atomic,
+        ..small_config()
+    };
+    compare_steps(&cfg, 10);
+}
+
+// Without regrowth the agents only ever remove nutriment
+#[test]
+fn nutriment_is_depleted() {
+    let (device, queue) = match device() {
+        Some(dq) => dq,
+        None => return
+    };
+    let cfg = Config {
+        consumption: 0.5,
+        r_init: 5.,
+        nutriment_image: Some(gradient_image()),
+        ..small_config()
+    };
+    let mut sim = Simulation::new(&cfg, device, queue,
+                                  wgpu::TextureFormat::Rgba8UnormSrgb)
+        .unwrap();
+    sim.step(20);
+
+    let capacity = sim.read_nutriment_capacity();
+    let nutriment = sim.read_nutriment();
+    assert!(nutriment.iter().zip(&capacity).all(|(n, c)| n <= c));
+    let total: f32 = nutriment.iter().sum();
+    assert!(total < capacity.iter().sum::<f32>());
+}
+
+// Eating depends on the agents passing, not on their trails
+#[test]
+fn agents_without_trails_eat() {
+    let (device, queue) = match device() {
+        Some(dq) => dq,
+        None => return
+    };
+    let cfg = Config {
+        deposit: 0.,
+        consumption: 0.1,
+        n_fix: 1,
+        r_fix_min: 10,
+        r_fix_max: 11,
+        ..small_config()
+    };
+    let mut sim = Simulation::new(&cfg, device, queue,
+                                  wgpu::TextureFormat::Rgba8UnormSrgb)
+        .unwrap();
+    let mut cpu = cpu_state(&sim);
+    sim.step(10);
+    for _ in 0 .. 10 {
+        cpu.step();
+    }
+
+    assert!(sim.read_slime().iter().all(|&s| s == 0.));
+    let capacity: f32 = sim.read_nutriment_capacity().iter().sum();
+    assert!(capacity > 0.);
+    for nutriment in [sim.read_nutriment(), cpu.nutriment] {
+        assert!(nutriment.iter().sum::<f32>() < capacity);
+    }
+}
+
+// The parameters of each step are uploaded before it is run
+#[test]
+fn timeline_matches_cpu() {
+    let cfg = Config {
+        timeline: vec![
+            Keyframe { step: 4, v: Some(4.), decay: Some(0.5),
+                       ..Keyframe::default() },
+            Keyframe { step: 8, phi_sens: Some(1.), w_nutriment: Some(-1.),
+                       ..Keyframe::default() }
+        ],
+        deposit_mode: DepositMode::Atomic,
+        ..small_config()
+    };
+    compare_steps(&cfg, 10);
+
+    let (device, queue) = match device() {
+        Some(dq) => dq,
+        None => return
+    };
+    let mut sim = Simulation::new(&cfg, device, queue,
+                                  wgpu::TextureFormat::Rgba8UnormSrgb)
+        .unwrap();
+    sim.step(2);
+    assert_eq!(sim.steps(), 2);
+    assert!((sim.species()[0].v - 2.75).abs() < 1e-6);
+    assert!((sim.uniforms().decay - 0.7).abs() < 1e-6);
+    sim.step(10);
+    assert_eq!(sim.species()[0].v, 4.);
+    assert_eq!(sim.species()[0].w_nutriment, -1.);
+}
+
+// Several animated steps in one call run as single steps do
+#[test]
+fn timeline_steps_at_once() {
+    let (device, queue) = match device() {
+        Some(dq) => dq,
+        None => return
+    };
+    let cfg = Config {
+        timeline: vec![Keyframe { step: 6, v: Some(4.), decay: Some(0.5),
+                                  ..Keyframe::default() }],
+        deposit_mode: DepositMode::Atomic,
+        ..small_config()
+    };
+    let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+    let mut at_once = Simulation::new(&cfg, device.clone(), queue.clone(),
+                                      format).unwrap();
+    let mut single = Simulation::new(&cfg, device, queue, format).unwrap();
+    at_once.step(10);
+    for _ in 0 .. 10 {
+        single.step(1);
+    }
+    assert_eq!(at_once.read_slime(), single.read_slime());
+}
+
+// Live edits change the parameters, not the run setup
+#[test]
+fn parameters_are_set_live() {
+    let (device, queue) = match device() {
+        Some(dq) => dq,
+        None => return
+    };
+    let mut sim = Simulation::new(&small_config(), device, queue,
+                                  wgpu::TextureFormat::Rgba8UnormSrgb)
+        .unwrap();
+    sim.step(3);
+    let edited = Config {
+        v: 3.,
+        decay: 0.6,
+        n_agents: Some(10),
+        ..sim.config().clone()
+    };
+    sim.set_parameters(&edited).unwrap();
+    assert_eq!(sim.species()[0].v, 3.);
+    assert_eq!(sim.uniforms().decay, 0.6);
+    assert_eq!(sim.n_agents(), 1000);
+
+    // the shaders use the new values from the next step on
+    let mut cpu = cpu_state(&sim);
+    cpu.step();
+    sim.step(1);
+    let differing = sim.read_agents().iter().zip(&cpu.agents)
+        .filter(|(a, b)| agent_differs(a, b))
+        .count();
+    assert!(differing as f32 <= MAX_MISMATCH * 1000.);
+}
+
+// Nutriment image, black on the left and a colour gradient on the right
+fn gradient_image() -> String {
+    let dir = std::env::temp_dir().join("physarum_image_maps");
+    std::fs::create_dir_all(&dir).unwrap();
+    let gradient = image::RgbImage::from_fn(61, 47, |x, _| {
+        if x < 30 {
+            image::Rgb([0, 0, 0])
+        } else {
+            image::Rgb([(x * 4) as u8, 100, 0])
+        }
+    });
+    let path = dir.join("gradient.png");
+    gradient.save(&path).unwrap();
+    path.to_string_lossy().into()
+}
+
+// Walls across the grid with a gap and a nutriment gradient from images
+#[test]
+fn image_maps_match_cpu() {
+    let dir = std::env::temp_dir().join("physarum_image_maps");
+    std::fs::create_dir_all(&dir).unwrap();
+    let walls = image::GrayImage::from_fn(61, 47, |x, y| {
+        let wall = (x == 20 || x == 21) && !(20 .. 26).contains(&y);
+        image::Luma([if wall { 255 } else { 0 }])
+    });
+    let obstacle_image = dir.join("walls.png");
+    walls.save(&obstacle_image).unwrap();
+
+    let cfg = Config {
+        obstacle_image: Some(obstacle_image.to_string_lossy().into()),
+        nutriment_image: Some(gradient_image()),
+        deposit_mode: DepositMode::Atomic,
+        ..small_config()
+    };
+    compare_steps(&cfg, 10);
+}
+
+// Walls everywhere but a cross of free cells through the centre
+fn walled_image() -> String {
+    let dir = std::env::temp_dir().join("physarum_walled");
+    std::fs::create_dir_all(&dir).unwrap();
+    let walls = image::GrayImage::from_fn(61, 47, |x, y| {
+        let free = (20 .. 26).contains(&y) || (28 .. 33).contains(&x);
+        image::Luma([if free { 0 } else { 255 }])
+    });
+    let path = dir.join("walled.png");
+    walls.save(&path).unwrap();
+    path.to_string_lossy().into()
+}
+
+// Neither the start, a respawn nor clamping puts agents into walls
+#[test]
+fn agents_stay_out_of_walls() {
+    let obstacle_image = walled_image();
+    for boundary in [Boundary::Clamp, Boundary::Wrap, Boundary::Reflect,
+                     Boundary::Respawn, Boundary::Absorb] {
+        let cfg = Config {
+            boundary,
+            v: 3.,
+            obstacle_image: Some(obstacle_image.clone()),
+            deposit_mode: DepositMode::Atomic,
+            ..small_config()
+        };
+        compare_steps(&cfg, 10);
+
+        let (device, queue) = match device() {
+            Some(dq) => dq,
+            None => return
+        };
+        let mut sim = Simulation::new(&cfg, device, queue,
+                                      wgpu::TextureFormat::Rgba8UnormSrgb)
+            .unwrap();
+        let obstacles = sim.read_obstacles();
+        let (size_x, _) = sim.size();
+        for step in 0 .. 40 {
+            for a in sim.read_agents().iter().filter(|a| a.x != ABSORBED) {
+                let cell = a.x as usize + a.y as usize * size_x as usize;
+                assert_eq!(obstacles[cell], 0,
+                           "{:?}: agent in a wall at ({}, {}) in step {}",
+                           boundary, a.x, a.y, step);
+            }
+            sim.step(1);
+        }
+    }
+}
+
+// Agents carry the image colour of the cell they are in
+#[test]
+fn agents_sample_image_colors() {
+    let (device, queue) = match device() {
+        Some(dq) => dq,
+        None => return
+    };
+    let cfg = Config {
+        nutriment_image: Some(gradient_image()),
+        color_sampling: ColorSampling::Continuous,
+        ..small_config()
+    };
+    let mut sim = Simulation::new(&cfg, device, queue,
+                                  wgpu::TextureFormat::Rgba8UnormSrgb)
+        .unwrap();
+    sim.step(5);
+
+    let (size_x, _) = sim.size();
+    let image = sim.read_nutriment_color();
+    for a in sim.read_agents() {
+        let pixel = image[a.x as usize + a.y as usize * size_x as usize];
+        assert_eq!((a.color.r, a.color.g, a.color.b),
+                   (pixel.r, pixel.g, pixel.b));
+    }
+}
+
+#[test]
+fn atomic_deposit_is_deterministic() {
+    let (device, queue) = match device() {
+        Some(dq) => dq,
+        None => return
+    };
+    let cfg = Config {
+        deposit_mode: DepositMode::Atomic,
+        n_agents: Some(5000),
+        ..small_config()
+    };
+    let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+    let mut runs = [0, 1].map(|_| Simulation::new(&cfg, device.clone(),
+                                                  queue.clone(), format)
+                                      .unwrap());
+    for sim in runs.iter_mut() {
+        sim.step(50);
+    }
+    let slime = runs.map(|sim| sim.read_slime());
+    assert!(slime[0] == slime[1]);
+}
+
+// Grids and agent counts the device can't hold are reported, not panics
+#[test]
+fn device_limits_are_reported() {
+    let (device, queue) = match device() {
+        Some(dq) => dq,
+        None => return
+    };
+    let cfg = Config {
+        size_x: Some(device.limits().max_texture_dimension_2d + 1),
+        size_y: Some(16),
+        n_agents: Some(u32::MAX),
+        ..small_config()
+    };
+    match Simulation::new(&cfg, device, queue,
+                          wgpu::TextureFormat::Rgba8UnormSrgb) {
+        Err(ConfigError::Invalid(errors)) => {
+            assert_eq!(errors.len(), 2, "{:?}", errors);
+            assert!(errors[0].starts_with("size_x"), "{:?}", errors);
+            assert!(errors[1].starts_with("n_agents"), "{:?}", errors);
+        }
+        Err(e) => panic!("expected invalid values, got {}", e),
+        Ok(_) => panic!("expected invalid values")
+    }
+}
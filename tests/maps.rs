@@ -0,0 +1,49 @@
+// Nutriment and obstacle maps read from images
+use physarum_art::maps::{obstacles_from_image, Nutriment};
+use physarum_art::ConfigError;
+
+
+fn save(name: &str, image: &image::RgbaImage) -> String {
+    let dir = std::env::temp_dir().join("physarum_maps");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    image.save(&path).unwrap();
+    path.to_string_lossy().into()
+}
+
+#[test]
+fn nutriment_from_luminance_and_color() {
+    let image = image::RgbaImage::from_raw(3, 1, vec![
+        255, 255, 255, 255,
+        255, 0, 0, 255,
+        0, 0, 255, 0  // transparent
+    ]).unwrap();
+    let path = save("nutriment.png", &image);
+
+    let nutriment = Nutriment::from_image(&path, 3, 1).unwrap();
+    assert!((nutriment.strength[0] - 1.).abs() < 1e-6);
+    assert!((nutriment.strength[1] - 0.2126).abs() < 1e-6);
+    assert_eq!(nutriment.strength[2], 0.);
+    assert_eq!((nutriment.color[1].r, nutriment.color[1].g), (1., 0.));
+}
+
+#[test]
+fn image_is_stretched_to_the_grid() {
+    let image = image::RgbaImage::from_fn(4, 4, |x, _| {
+        if x < 2 { image::Rgba([255; 4]) } else { image::Rgba([0, 0, 0, 255]) }
+    });
+    let path = save("obstacles.png", &image);
+
+    let walls = obstacles_from_image(&path, 16, 2).unwrap();
+    assert_eq!(walls.len(), 32);
+    assert_eq!(&walls[.. 4], &[1; 4]);
+    assert_eq!(&walls[12 .. 16], &[0; 4]);
+}
+
+#[test]
+fn missing_image_is_reported() {
+    match Nutriment::from_image("does/not/exist.png", 8, 8) {
+        Err(ConfigError::Image(path, _)) => assert!(path.contains("exist")),
+        _ => panic!("expected an image error")
+    }
+}
@@ -0,0 +1,258 @@
+// Render layers and post processing of the rendered trails
+use std::sync::mpsc;
+
+use physarum_art::config::{AgentColoring, BlendMode, Layer, Layers};
+use physarum_art::{Config, Simulation};
+
+mod common;
+use common::device;
+
+
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+// 64 RGBA8 pixels fill a row of a texture copy
+const SIZE: u32 = 64;
+
+fn trails() -> Config {
+    Config {
+        r_init: 10.,
+        deposit: 0.2,
+        decay: 0.9,
+        size_x: Some(SIZE),
+        size_y: Some(SIZE),
+        n_agents: Some(500),
+        seed: Some(7),
+        ..Config::default()
+    }
+}
+
+// Pixels of the simulation rendered after steps, RGBA rows
+fn render(cfg: &Config, steps: u32) -> Option<Vec<u8>> {
+    let (device, queue) = device()?;
+    let mut sim = Simulation::new(cfg, device.clone(), queue.clone(), FORMAT)
+        .unwrap();
+    sim.step(steps);
+
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: None,
+        size: wgpu::Extent3d {
+            width: SIZE,
+            height: SIZE,
+            depth_or_array_layers: 1
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT |
+               wgpu::TextureUsages::COPY_SRC
+    });
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: (4 * SIZE * SIZE) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let mut encoder = device.create_command_encoder(
+        &wgpu::CommandEncoderDescriptor { label: None });
+    sim.encode_render(&mut encoder, &view);
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::ImageCopyBuffer {
+            buffer: &buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(4 * SIZE),
+                rows_per_image: None
+            }
+        },
+        wgpu::Extent3d {
+            width: SIZE,
+            height: SIZE,
+            depth_or_array_layers: 1
+        });
+    queue.submit(Some(encoder.finish()));
+
+    let slice = buffer.slice(..);
+    let (tx, rx) = mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |res| tx.send(res).unwrap());
+    device.poll(wgpu::Maintain::Wait);
+    rx.recv().unwrap().unwrap();
+    let pixels = slice.get_mapped_range().to_vec();
+    Some(pixels)
+}
+
+fn brightness(pixels: &[u8]) -> u64 {
+    pixels.chunks(4).map(|p| p[0] as u64 + p[1] as u64 + p[2] as u64).sum()
+}
+
+#[test]
+fn bloom_spreads_light() {
+    let plain = match render(&trails(), 20) {
+        Some(pixels) => pixels,
+        None => return
+    };
+    let cfg = Config { bloom: 2., bloom_threshold: 0.1, ..trails() };
+    let bloom = render(&cfg, 20).unwrap();
+
+    assert!(brightness(&bloom) > brightness(&plain));
+    // cells without trails glow next to them
+    let lit = |pixels: &[u8]| pixels.chunks(4).filter(|p| p[0] > 0).count();
+    assert!(lit(&bloom) > lit(&plain), "{} {}", lit(&bloom), lit(&plain));
+    assert!(plain.chunks(4).zip(bloom.chunks(4)).all(|(p, b)| b[0] >= p[0]));
+}
+
+#[test]
+fn vignette_darkens_the_edges() {
+    let uniform = Config { r_init: 500., n_agents: Some(20000), ..trails() };
+    let plain = match render(&uniform, 10) {
+        Some(pixels) => pixels,
+        None => return
+    };
+    let cfg = Config { vignette: 1., ..uniform };
+    let vignette = render(&cfg, 10).unwrap();
+
+    let row = (SIZE / 2 * SIZE * 4) as usize;
+    let center = row + (SIZE / 2 * 4) as usize;
+    assert!(vignette[center] as i32 >= plain[center] as i32 - 1);
+    // top 4 rows
+    let corner = |pixels: &[u8]| {
+        brightness(&pixels[.. (4 * 4 * SIZE) as usize])
+    };
+    assert!(corner(&vignette) < corner(&plain) / 2);
+}
+
+#[test]
+fn grain_changes_with_the_frame() {
+    let cfg = Config { grain: 0.5, r_init: 500., n_agents: Some(20000),
+                       ..trails() };
+    let first = match render(&cfg, 10) {
+        Some(pixels) => pixels,
+        None => return
+    };
+    let second = render(&cfg, 11).unwrap();
+    let again = render(&cfg, 10).unwrap();
+    assert_eq!(first, again);
+    assert_ne!(first, second);
+}
+
+fn lit(pixels: &[u8]) -> usize {
+    pixels.chunks(4).filter(|p| p[0] > 0 || p[1] > 0 || p[2] > 0).count()
+}
+
+// Without trails only the sprites are visible
+#[test]
+fn agents_are_drawn_as_sprites() {
+    let no_trails = Config { deposit: 0., n_agents: Some(50), ..trails() };
+    let hidden = match render(&no_trails, 1) {
+        Some(pixels) => pixels,
+        None => return
+    };
+    assert_eq!(lit(&hidden), 0);
+
+    let agents = Layer { visible: true, opacity: 1., ..Layer::default() };
+    let layers = Layers { agents, ..Layers::default() };
+    let points = render(&Config { layers, ..no_trails.clone() }, 1).unwrap();
+    assert!((1 ..= 50).contains(&lit(&points)), "{}", lit(&points));
+    // white before any nutriment colour is taken
+    assert!(points.chunks(4).all(|p| p[0] == p[1] && p[1] == p[2]));
+
+    let layers = Layers { agent_size: 5., ..layers };
+    let sprites = render(&Config { layers, ..no_trails.clone() }, 1).unwrap();
+    assert!(lit(&sprites) > lit(&points));
+
+    let layers = Layers { agent_coloring: AgentColoring::Heading, ..layers };
+    let heading = render(&Config { layers, ..no_trails }, 1).unwrap();
+    assert!(heading.chunks(4).any(|p| p[0] != p[2]));
+}
+
+// Red nutriment on the left half, a wall along the bottom row, images
+// saved in a directory of their own for each test
+fn maps(test: &str) -> Config {
+    let dir = std::env::temp_dir().join("physarum_render_layers").join(test);
+    std::fs::create_dir_all(&dir).unwrap();
+    let nutriment = image::RgbImage::from_fn(SIZE, SIZE, |x, _| {
+        if x < SIZE / 2 { image::Rgb([255, 0, 0]) } else { image::Rgb([0; 3]) }
+    });
+    let walls = image::GrayImage::from_fn(SIZE, SIZE, |_, y| {
+        image::Luma([if y == SIZE - 1 { 255 } else { 0 }])
+    });
+    let nutriment_path = dir.join("nutriment.png");
+    let walls_path = dir.join("walls.png");
+    nutriment.save(&nutriment_path).unwrap();
+    walls.save(&walls_path).unwrap();
+    Config {
+        nutriment_image: Some(nutriment_path.to_string_lossy().into()),
+        obstacle_image: Some(walls_path.to_string_lossy().into()),
+        ..trails()
+    }
+}
+
+fn pixel(pixels: &[u8], x: u32, y: u32) -> &[u8] {
+    let i = (4 * (y * SIZE + x)) as usize;
+    &pixels[i .. i + 4]
+}
+
+#[test]
+fn nutriment_and_obstacle_layers() {
+    let shown = Layer::default();
+    let layers = Layers { nutriment: shown, obstacles: shown,
+                          trails: Layer { visible: false, ..shown },
+                          ..Layers::default() };
+    let pixels = match render(&Config { layers, ..maps("layers") }, 1) {
+        Some(pixels) => pixels,
+        None => return
+    };
+    let red = pixel(&pixels, 4, 4);
+    // strength of the luminance of red
+    assert!(red[0] > 40 && red[1] == 0 && red[2] == 0, "{:?}", red);
+    assert_eq!(&pixel(&pixels, SIZE - 4, 4)[.. 3], &[0; 3]);
+    assert_eq!(&pixel(&pixels, SIZE - 4, SIZE - 1)[.. 3], &[255; 3]);
+
+    // hidden layers leave the grid black
+    let layers = Layers { trails: Layer { visible: false, ..shown },
+                          ..Layers::default() };
+    let hidden = render(&Config { layers, ..maps("layers") }, 1).unwrap();
+    assert_eq!(lit(&hidden), 0);
+}
+
+// Trails multiplied onto the nutriment keep it only where they are
+#[test]
+fn multiply_layer_darkens() {
+    let nutriment = Layers { nutriment: Layer::default(),
+                             ..Layers::default() };
+    let added = match render(&Config { layers: nutriment, ..maps("multiply") }, 10) {
+        Some(pixels) => pixels,
+        None => return
+    };
+    let trails = Layer { blend: BlendMode::Multiply, ..Layer::default() };
+    let layers = Layers { trails, ..nutriment };
+    let multiplied = render(&Config { layers, ..maps("multiply") }, 10).unwrap();
+
+    assert!(brightness(&multiplied) < brightness(&added) / 2);
+    assert!(added.chunks(4).zip(multiplied.chunks(4))
+            .all(|(a, m)| m[0] <= a[0]));
+    assert!(lit(&multiplied) > 0);
+}
+
+#[test]
+fn layers_from_json() {
+    let cfg: Config = serde_json::from_str(r#"{"layers": {
+        "nutriment": {"visible": true, "blend": "screen"},
+        "trails": {"opacity": 0.5, "blend": "multiply"},
+        "agent_size": 3
+    }}"#).unwrap();
+    assert_eq!(cfg.layers.nutriment,
+               Layer { visible: true, opacity: 1., blend: BlendMode::Screen });
+    assert_eq!(cfg.layers.trails.opacity, 0.5);
+    assert_eq!(cfg.layers.trails.blend, BlendMode::Multiply);
+    assert!(!cfg.layers.agents.visible);
+    assert_eq!(cfg.layers.agent_size, 3.);
+    cfg.validate().unwrap();
+
+    let cfg = Config { layers: Layers { agent_size: 0.5,
+                                        ..Layers::default() },
+                       ..Config::default() };
+    assert!(cfg.validate().unwrap_err().to_string()
+            .contains("layers.agent_size"));
+}
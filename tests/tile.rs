@@ -0,0 +1,111 @@
+// Seam check on synthetic images and on frames of a wrapped simulation
+use physarum_art::config::{Boundary, Layer, Layers};
+use physarum_art::frame::Frame;
+use physarum_art::tile::Seams;
+use physarum_art::{Config, Simulation};
+
+mod common;
+
+
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+// Grey image from a function of the pixel position
+fn image(width: usize, height: usize, f: impl Fn(usize, usize) -> f32)
+-> Vec<u8> {
+    (0 .. width*height).map(|i| f(i % width, i / width) as u8).collect()
+}
+
+#[test]
+fn ramp_has_a_seam() {
+    let ramp = image(64, 32, |x, _| x as f32 * 4.);
+    let seams = Seams::measure(&ramp, 64, 32, 1);
+    assert!(seams.left_right > 10.);
+    assert_eq!(seams.top_bottom, 1.);
+    assert!(!seams.is_seamless());
+}
+
+#[test]
+fn periodic_image_is_seamless() {
+    let wave = |t: usize, n: usize|
+        (t as f32 / n as f32 * std::f32::consts::TAU).sin();
+    let periodic = image(64, 32,
+                         |x, y| 127. + 60.*wave(x, 64) + 60.*wave(y, 32));
+    let seams = Seams::measure(&periodic, 64, 32, 1);
+    assert!(seams.is_seamless(), "{:?}", seams);
+}
+
+// Frame rendered after steps, RGBA rows
+fn render(cfg: &Config, steps: u32) -> Option<Vec<u8>> {
+    let (device, queue) = common::device()?;
+    let mut sim = Simulation::new(cfg, device.clone(), queue.clone(), FORMAT)
+        .unwrap();
+    sim.step(steps);
+    let (size_x, size_y) = sim.size();
+    let frame = Frame::new(&device, size_x, size_y, FORMAT);
+    let mut encoder = device.create_command_encoder(
+        &wgpu::CommandEncoderDescriptor { label: None });
+    sim.encode_render(&mut encoder, &frame.view);
+    frame.copy_to_buffer(&mut encoder);
+    queue.submit(Some(encoder.finish()));
+    Some(frame.read_pixels(&device))
+}
+
+fn wrapped() -> Config {
+    Config {
+        boundary: Boundary::Wrap,
+        deposit: 0.05,
+        v: 2.,
+        size_x: Some(96),
+        size_y: Some(64),
+        n_agents: Some(20000),
+        seed: Some(7),
+        ..Config::default()
+    }
+}
+
+#[test]
+fn wrapped_simulation_is_seamless() {
+    let pixels = match render(&wrapped(), 300) {
+        Some(pixels) => pixels,
+        None => return
+    };
+    assert!(pixels.chunks(4).any(|p| p[0] > 0), "nothing rendered");
+    let seams = Seams::measure(&pixels, 96, 64, 4);
+    assert!(seams.is_seamless(), "{:?}", seams);
+}
+
+// The glow continues across the edges and there is no vignette
+#[test]
+fn post_processing_is_seamless() {
+    let cfg = Config {
+        bloom: 2.,
+        bloom_threshold: 0.2,
+        bloom_radius: 6.,
+        vignette: 1.,
+        ..wrapped()
+    };
+    let pixels = match render(&cfg, 300) {
+        Some(pixels) => pixels,
+        None => return
+    };
+    let seams = Seams::measure(&pixels, 96, 64, 4);
+    assert!(seams.is_seamless(), "{:?}", seams);
+}
+
+// Sprites crossing an edge continue at the opposite one
+#[test]
+fn sprites_are_seamless() {
+    let agents = Layer { visible: true, opacity: 0.5, ..Layer::default() };
+    let cfg = Config {
+        deposit: 0.,
+        n_agents: Some(3000),
+        layers: Layers { agents, agent_size: 8., ..Layers::default() },
+        ..wrapped()
+    };
+    let pixels = match render(&cfg, 5) {
+        Some(pixels) => pixels,
+        None => return
+    };
+    let seams = Seams::measure(&pixels, 96, 64, 4);
+    assert!(seams.is_seamless(), "{:?}", seams);
+}
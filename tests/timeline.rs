@@ -0,0 +1,68 @@
+// Keyframed parameters
+use physarum_art::timeline::{at_step, Interpolation, Keyframe};
+use physarum_art::{Config, ConfigError};
+
+
+fn animated_config() -> Config {
+    Config {
+        decay: 0.9,
+        v: 1.,
+        timeline: vec![
+            Keyframe { step: 10, decay: Some(0.5), ..Keyframe::default() },
+            Keyframe { step: 20, v: Some(3.),
+                       interpolation: Interpolation::Smooth,
+                       ..Keyframe::default() },
+            Keyframe { step: 30, decay: Some(0.7), ..Keyframe::default() }
+        ],
+        ..Config::default()
+    }
+}
+
+#[test]
+fn linear_between_keyframes() {
+    let cfg = animated_config();
+    let decay = |step| at_step(&cfg, step).decay;
+    assert_eq!(decay(0), 0.9);
+    assert!((decay(5) - 0.7).abs() < 1e-6);
+    assert_eq!(decay(10), 0.5);
+    // the keyframe at step 20 doesn't set decay
+    assert!((decay(20) - 0.6).abs() < 1e-6);
+    assert_eq!(decay(30), 0.7);
+    assert_eq!(decay(1000), 0.7);
+}
+
+#[test]
+fn smooth_eases_in_and_out() {
+    let cfg = animated_config();
+    let v = |step| at_step(&cfg, step).v;
+    assert_eq!(v(0), 1.);
+    assert!((v(10) - 2.).abs() < 1e-6);
+    // slower than linear close to the keyframes
+    assert!(v(2) - 1. < 0.2 * 2.);
+    assert!(3. - v(18) < 0.2 * 2.);
+    assert_eq!(v(25), 3.);
+}
+
+#[test]
+fn keyframes_are_validated() {
+    let mut cfg = animated_config();
+    cfg.timeline[1].step = 5;
+    cfg.timeline[2].decay = Some(1.5);
+    match cfg.validate() {
+        Err(ConfigError::Invalid(errors)) => {
+            assert_eq!(errors.len(), 2, "{:?}", errors);
+            assert!(errors[0].starts_with("timeline[1].step"));
+            assert!(errors[1].starts_with("timeline[2] (step 30): decay"));
+        }
+        other => panic!("{:?}", other)
+    }
+}
+
+#[test]
+fn timeline_from_json() {
+    let cfg: Config = serde_json::from_str(r#"{
+        "timeline": [{"step": 100, "interpolation": "smooth", "v": 2.0}]
+    }"#).unwrap();
+    assert_eq!(cfg.timeline[0].interpolation, Interpolation::Smooth);
+    assert_eq!(at_step(&cfg, 100).v, 2.);
+}